@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use solver::color::{Attribute, ColorMapping, Colormap, Range};
+use solver::export::RenderOptions;
+
+const USAGE: &str = "\
+usage: headless [options]
+
+  --scene <file>             scene to simulate, defaults to the dam break
+  --restart <file>           continue from a checkpoint instead of loading the scene
+  --frames <n>               number of frames to simulate
+  --seconds <t>              simulated time to run, defaults to the scene duration
+  --threads <n>              number of solver threads
+  --safeguard                roll back and retry frames that blow up with more substeps
+  --every <n>                export every n-th frame (default 1)
+  --vtk <dir>                write VTK frames and a .pvd index to dir
+  --npz <dir>                write NumPy .npz frames to dir
+  --npz-run <file>           write all exported frames of this run to one .npz file
+  --png <dir>                render frames to PNG images in dir
+  --size <w>x<h>             PNG resolution (default 1024x720)
+  --color-by <quantity>      color PNG particles by speed, pressure, density,
+                             density_error, vorticity, neighbors or temperature
+  --colormap <name>          viridis (default) or coolwarm
+  --range <min>:<max>        fixed value range of the colors, defaults to auto
+  --hide-obstacles           leave obstacles out of PNG images
+  --checkpoint <file>        write checkpoints to file
+  --checkpoint-every <n>     checkpoint every n-th frame (default 400)
+  --progress-every <n>       print progress every n-th frame (default 40)";
+
+/// Default run length without `--frames`, `--seconds` or a scene duration
+const DEFAULT_FRAMES: usize = 400;
+
+#[derive(Debug)]
+pub enum Length {
+    Frames(usize),
+    Seconds(f64),
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub scene: Option<PathBuf>,
+    pub restart: Option<PathBuf>,
+    pub length: Option<Length>,
+    pub threads: Option<usize>,
+    pub safeguard: bool,
+    pub export_every: usize,
+    pub vtk_dir: Option<PathBuf>,
+    pub npz_dir: Option<PathBuf>,
+    pub npz_run: Option<PathBuf>,
+    pub png_dir: Option<PathBuf>,
+    pub render: RenderOptions,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_every: usize,
+    pub progress_every: usize,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut parsed = Self {
+            scene: None,
+            restart: None,
+            length: None,
+            threads: None,
+            safeguard: false,
+            export_every: 1,
+            vtk_dir: None,
+            npz_dir: None,
+            npz_run: None,
+            png_dir: None,
+            render: RenderOptions::default(),
+            checkpoint: None,
+            checkpoint_every: 400,
+            progress_every: 40,
+        };
+        let (mut color_by, mut colormap, mut range) = (None, Colormap::Viridis, Range::Auto);
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {arg}\n{USAGE}"))
+            };
+            match arg.as_str() {
+                "--scene" => parsed.scene = Some(value()?.into()),
+                "--restart" => parsed.restart = Some(value()?.into()),
+                "--frames" => parsed.length = Some(Length::Frames(parse_count(&value()?)?)),
+                "--seconds" => {
+                    let value = value()?;
+                    let seconds = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|s| *s >= 0.0)
+                        .ok_or_else(|| format!("Invalid duration {value}"))?;
+                    parsed.length = Some(Length::Seconds(seconds));
+                }
+                "--threads" | "-j" => parsed.threads = Some(parse_count(&value()?)?),
+                "--safeguard" => parsed.safeguard = true,
+                "--every" => parsed.export_every = parse_count(&value()?)?.max(1),
+                "--vtk" => parsed.vtk_dir = Some(value()?.into()),
+                "--npz" => parsed.npz_dir = Some(value()?.into()),
+                "--npz-run" => parsed.npz_run = Some(value()?.into()),
+                "--png" => parsed.png_dir = Some(value()?.into()),
+                "--size" => {
+                    let value = value()?;
+                    let (width, height) = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|(w, h)| *w > 0 && *h > 0)
+                        .ok_or_else(|| format!("Invalid image size {value}"))?;
+                    parsed.render.width = width;
+                    parsed.render.height = height;
+                }
+                "--color-by" => {
+                    let value = value()?;
+                    color_by = Some(
+                        Attribute::from_name(&value)
+                            .ok_or_else(|| format!("Unknown quantity {value}"))?,
+                    );
+                }
+                "--colormap" => {
+                    let value = value()?;
+                    colormap = Colormap::from_name(&value)
+                        .ok_or_else(|| format!("Unknown colormap {value}"))?;
+                }
+                "--range" => {
+                    let value = value()?;
+                    range = Range::parse(&value).ok_or_else(|| format!("Invalid range {value}"))?;
+                }
+                "--hide-obstacles" => parsed.render.obstacle_color = None,
+                "--checkpoint" => parsed.checkpoint = Some(value()?.into()),
+                "--checkpoint-every" => parsed.checkpoint_every = parse_count(&value()?)?.max(1),
+                "--progress-every" => parsed.progress_every = parse_count(&value()?)?.max(1),
+                "--help" | "-h" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("Unknown argument {arg}\n{USAGE}")),
+            }
+        }
+        parsed.render.color_mapping = color_by.map(|attribute| ColorMapping {
+            attribute,
+            colormap,
+            range,
+        });
+        Ok(parsed)
+    }
+
+    /// Total number of frames of the run, counted from time zero.
+    pub fn total_frames(&self, scene_duration: Option<f64>, frame_dt: f64) -> usize {
+        let seconds = match self.length {
+            Some(Length::Frames(frames)) => return frames,
+            Some(Length::Seconds(seconds)) => seconds,
+            None => match scene_duration {
+                Some(seconds) => seconds,
+                None => return DEFAULT_FRAMES,
+            },
+        };
+        (seconds / frame_dt).round() as usize
+    }
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|e| format!("Invalid count {value}: {e}"))
+}
@@ -0,0 +1,218 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use log::info;
+use solver::export::{NpzRecorder, VtkSeries};
+
+mod args;
+
+use args::Args;
+
+const DAM_PARTICLES: usize = 75 * 75;
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(2);
+    });
+    if let Err(e) = run(&args) {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let scene = args
+        .scene
+        .as_ref()
+        .map(|path| {
+            solver::Scene::load(path)
+                .map_err(|e| format!("Failed to load scene {}: {e}", path.display()))
+        })
+        .transpose()?;
+    let mut sim = match args.threads {
+        Some(n) => solver::State::with_num_threads(n)
+            .map_err(|e| format!("Failed to create solver thread pool: {e}"))?,
+        None => solver::State::new(),
+    };
+    match (&args.restart, &scene) {
+        (Some(path), _) => {
+            File::open(path)
+                .and_then(|file| sim.load_checkpoint(BufReader::new(file)))
+                .map_err(|e| format!("Failed to load checkpoint {}: {e}", path.display()))?;
+            // checkpoints saved from a paused frontend would otherwise never advance
+            sim.set_paused(false);
+            eprintln!(
+                "Restarted from {} at t = {:.3}s",
+                path.display(),
+                sim.time()
+            );
+        }
+        (None, Some(scene)) => sim.load_scene(scene).map_err(|e| e.to_string())?,
+        (None, None) => sim
+            .try_init_dam_break(DAM_PARTICLES)
+            .map_err(|e| e.to_string())?,
+    }
+    sim.set_timings_enabled(true);
+    if args.safeguard {
+        sim.set_safeguard(Some(solver::Safeguard::default()));
+    }
+
+    let frame_dt = solver::to_f64(sim.params().frame_dt);
+    let first_frame = (solver::to_f64(sim.time()) / frame_dt).round() as usize;
+    let total_frames = args.total_frames(
+        scene.as_ref().and_then(|s| s.duration).map(solver::to_f64),
+        frame_dt,
+    );
+    if first_frame >= total_frames {
+        return Err(format!(
+            "Nothing to simulate: starting at frame {first_frame} but the run ends at frame {total_frames}"
+        ));
+    }
+    eprintln!(
+        "Simulating frames {first_frame} to {total_frames} with {} particles on {} threads",
+        sim.particles.len(),
+        sim.num_threads()
+    );
+
+    let mut vtk = args
+        .vtk_dir
+        .as_ref()
+        .map(|dir| VtkSeries::resume(dir, "frame", sim.time()))
+        .transpose()
+        .map_err(|e| format!("Failed to open VTK series: {e}"))?;
+    if let Some(dir) = &args.npz_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create npz output directory: {e}"))?;
+    }
+    if let Some(dir) = &args.png_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create PNG output directory: {e}"))?;
+    }
+    let mut npz_run = args.npz_run.as_ref().map(|_| NpzRecorder::new());
+
+    let start = Instant::now();
+    for frame in first_frame..=total_frames {
+        if frame % args.export_every == 0 {
+            if let Some(vtk) = vtk.as_mut() {
+                let path = vtk
+                    .write_frame(frame, &sim)
+                    .map_err(|e| format!("Failed to write VTK frame: {e}"))?;
+                info!("Wrote {}", path.display());
+            }
+            if let Some(dir) = &args.npz_dir {
+                let path = dir.join(format!("frame_{frame:06}.npz"));
+                File::create(&path)
+                    .and_then(|file| sim.write_npz(BufWriter::new(file)))
+                    .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+                info!("Wrote {}", path.display());
+            }
+            if let Some(dir) = &args.png_dir {
+                let path = dir.join(format!("frame_{frame:06}.png"));
+                File::create(&path)
+                    .and_then(|file| sim.write_png(BufWriter::new(file), &args.render))
+                    .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+                info!("Wrote {}", path.display());
+            }
+            if let Some(recorder) = npz_run.as_mut() {
+                recorder
+                    .record(&sim)
+                    .map_err(|e| format!("Failed to record frame: {e}"))?;
+            }
+        }
+        if frame == total_frames {
+            break;
+        }
+
+        sim.try_update()
+            .map_err(|e| format!("Simulation failed at frame {frame}: {e}"))?;
+        match sim.last_recovery() {
+            Some(recovery) if recovery.recovered => eprintln!("Frame {frame}: {recovery}"),
+            Some(recovery) => {
+                return Err(format!("Simulation failed at frame {frame}: {recovery}"))
+            }
+            None => (),
+        }
+
+        let done = frame + 1;
+        if let Some(path) = &args.checkpoint {
+            if done % args.checkpoint_every == 0 || done == total_frames {
+                write_checkpoint(&sim, path)?;
+                info!("Wrote checkpoint {} at frame {done}", path.display());
+            }
+        }
+        if done % args.progress_every == 0 || done == total_frames {
+            print_progress(&sim, first_frame..total_frames, done, start.elapsed());
+        }
+    }
+
+    if let (Some(recorder), Some(path)) = (npz_run, &args.npz_run) {
+        File::create(path)
+            .and_then(|file| recorder.write(BufWriter::new(file)))
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        info!(
+            "Wrote {} frames to {}",
+            recorder.num_frames(),
+            path.display()
+        );
+    }
+    if let Some(timings) = sim.timings() {
+        print_timings(timings, total_frames - first_frame, start.elapsed());
+    }
+    Ok(())
+}
+
+/// Writes to a temporary file first so that an interrupted write never
+/// replaces the previous checkpoint with a truncated one.
+fn write_checkpoint(sim: &solver::State, path: &Path) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    File::create(&tmp)
+        .and_then(|file| sim.save_checkpoint(BufWriter::new(file)))
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write checkpoint {}: {e}", path.display()))
+}
+
+fn print_progress(sim: &solver::State, frames: Range<usize>, done: usize, elapsed: Duration) {
+    let rate = (done - frames.start) as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    let eta = (frames.end - done) as f64 / rate.max(f64::EPSILON);
+    eprintln!(
+        "frame {done:>6}/{} ({:>5.1}%)  t = {:>8.3}s  particles = {:>5}  {rate:>7.1} frames/s  eta {eta:.0}s",
+        frames.end,
+        100.0 * done as f64 / frames.end.max(1) as f64,
+        sim.time(),
+        sim.particles.len(),
+    );
+}
+
+fn print_timings(timings: &solver::PhaseTimings, frames: usize, elapsed: Duration) {
+    let total = timings.total().as_secs_f64().max(f64::EPSILON);
+    let frames = frames.max(1) as f64;
+    eprintln!(
+        "Simulated {frames} frames ({} solver steps) in {:.2}s",
+        timings.steps,
+        elapsed.as_secs_f64()
+    );
+    for (name, duration) in [
+        ("emit", timings.emit),
+        ("integrate_insert", timings.integrate_insert),
+        ("compute_forces", timings.compute_forces),
+        ("project_correct", timings.project_correct),
+        ("transfer_heat", timings.heat),
+        ("transport", timings.transport),
+        ("vorticity", timings.vorticity),
+    ] {
+        let secs = duration.as_secs_f64();
+        eprintln!(
+            "  {name:<16} {:>9.3}s  {:>8.3} ms/frame  {:>5.1}%",
+            secs,
+            1000.0 * secs / frames,
+            100.0 * secs / total
+        );
+    }
+}
@@ -31,7 +31,11 @@ import { HandlersWrap } from './wasm-worker';
     const stats = new Stats();
     stats.dom.style.position = 'absolute';
     const simPanel = stats.addPanel(new Stats.Panel('MS (Sim)', '#ff8', '#221'));
-    stats.showPanel(stats.dom.children.length - 1); // ms per sim step
+    const diagnosticPanels = {
+        densityError: stats.addPanel(new Stats.Panel('% MAX ρ ERR', '#f8f', '#212')),
+        maxSpeed: stats.addPanel(new Stats.Panel('MAX SPEED', '#8ff', '#122')),
+    };
+    stats.showPanel(stats.dom.children.length - 3); // ms per sim step, click to cycle
     $('container').appendChild(stats.dom);
 
     // attach controls window
@@ -41,12 +45,53 @@ import { HandlersWrap } from './wasm-worker';
         threads: await handlers.numThreads,
         particles: 0,
         block: async () => {
-            setInfo(await handlers.addBlock());
+            try {
+                setInfo(await handlers.addBlock());
+            } catch (e) {
+                console.warn(`Cannot add block: ${e}`);
+            }
         },
         reset: async () => {
             setInfo(await handlers.reset());
+            await syncParameters();
+        },
+        scene: () => sceneInput.click(),
+        render: 'Points',
+        colorBy: 'none',
+        colormap: (await handlers.colormaps)[0],
+        autoRange: true,
+        min: 0,
+        max: 1,
+        pointer: 'Drag',
+        paused: false,
+        safeguard: true,
+        timeScale: 1,
+        time: '0.00 s',
+        stepFrame: async () => {
+            await handlers.stepFrame();
+        },
+        stepSubstep: async () => {
+            await handlers.stepSubstep();
         },
     };
+    // scene files are read on the main thread and passed to the worker as text
+    const sceneInput = document.createElement('input');
+    sceneInput.type = 'file';
+    sceneInput.accept = '.toml';
+    sceneInput.onchange = async () => {
+        const file = sceneInput.files[0];
+        sceneInput.value = '';
+        if (!file) {
+            return;
+        }
+        try {
+            setInfo(await handlers.loadScene(await file.text()));
+            await syncParameters();
+        } catch (e) {
+            console.error(e);
+            alert(`Failed to load scene: ${e}`);
+        }
+    };
     const particlesControl = gui.add(props, 'particles').disable();
     const setInfo = (numParticles: number) => {
         props.particles = numParticles;
@@ -55,10 +100,102 @@ import { HandlersWrap } from './wasm-worker';
     gui.add(props, 'threads').disable();
     gui.add(props, 'block').name("add block");
     gui.add(props, 'reset').name("reset simulation");
+    gui.add(props, 'scene').name("load scene");
+    gui.add(props, 'pointer', ['Drag', 'Attract', 'Repel', 'Erase']).name("pointer mode");
+    // playback, stepping works while paused
+    const playback = gui.addFolder('playback');
+    const pausedControl = playback.add(props, 'paused').onChange(async (paused: boolean) => {
+        await handlers.setPaused(paused);
+    });
+    // rolls back and retries frames that blow up, see the console for reports
+    playback.add(props, 'safeguard').onChange(async (enabled: boolean) => {
+        await handlers.setSafeguard(enabled);
+    });
+    playback.add(props, 'timeScale', 0.1, 4, 0.05).name("time scale").onChange(async (timeScale: number) => {
+        await handlers.setTimeScale(timeScale);
+    });
+    playback.add(props, 'stepFrame').name("step frame");
+    playback.add(props, 'stepSubstep').name("step substep");
+    const timeControl = playback.add(props, 'time').disable();
+    setInterval(async () => {
+        props.time = `${(await handlers.time()).toFixed(2)} s`;
+        timeControl.updateDisplay();
+        props.paused = await handlers.paused();
+        pausedControl.updateDisplay();
+    }, 100);
+    gui.add(props, 'render', ['Points', 'Metaballs']).name("render mode").onChange(async (mode: 'Points' | 'Metaballs') => {
+        await handlers.setRenderMode(mode);
+    });
+    // solver parameter controls are generated from the metadata exported by the solver
+    const parameters = gui.addFolder('parameters').close();
+    const parameterInfo = await handlers.parameters;
+    const parameterProps: Record<string, number> = Object.fromEntries(parameterInfo.map((info) => [info.name, info.default]));
+    for (const info of parameterInfo) {
+        parameters.add(parameterProps, info.name, info.min, info.max, info.step).name(info.name.replace(/_/g, ' ')).onChange(async (value: number) => {
+            await handlers.setParameter(info.name, value);
+        });
+    }
+    parameters.add({
+        defaults: async () => {
+            for (const info of parameterInfo) {
+                await handlers.setParameter(info.name, info.default);
+            }
+            await syncParameters();
+        }
+    }, 'defaults').name("restore defaults");
+    const syncParameters = async () => {
+        Object.assign(parameterProps, await handlers.parameterValues());
+        parameters.controllersRecursive().forEach((c) => c.updateDisplay());
+    };
+    // per-particle color mapping, shown in the points render mode
+    const colors = gui.addFolder('colors');
+    const setColorRange = async () => {
+        await handlers.setColorRange(props.autoRange ? null : [props.min, props.max]);
+    };
+    colors.add(props, 'colorBy', ['none', ...(await handlers.colorAttributes)]).name("color by").onChange(async (attribute: string) => {
+        await handlers.setColorBy(attribute);
+        await handlers.setColormap(props.colormap);
+        await setColorRange();
+    });
+    colors.add(props, 'colormap', await handlers.colormaps).onChange(async (name: string) => {
+        await handlers.setColormap(name);
+    });
+    colors.add(props, 'autoRange').name("auto range").onChange(setColorRange);
+    const minControl = colors.add(props, 'min').onChange(setColorRange);
+    const maxControl = colors.add(props, 'max').onChange(setColorRange);
+    // follow the automatic range so that switching to a fixed one starts from it
+    setInterval(async () => {
+        const range = await handlers.colorRange();
+        if (props.autoRange && range.length == 2) {
+            [props.min, props.max] = range;
+            minControl.updateDisplay();
+            maxControl.updateDisplay();
+        }
+    }, 500);
     $('gui').appendChild(gui.domElement);
 
+    // primary button uses the selected pointer mode, secondary attracts or repels with shift
+    canvas.addEventListener('contextmenu', (e) => e.preventDefault());
+    canvas.addEventListener('pointerdown', async (e) => {
+        const mode = e.button == 2 ? (e.shiftKey ? 'Repel' : 'Attract') : props.pointer as 'Drag' | 'Attract' | 'Repel' | 'Erase';
+        canvas.setPointerCapture(e.pointerId);
+        await handlers.pointerDown(e.offsetX, e.offsetY, canvas.clientWidth, canvas.clientHeight, mode);
+    });
+    canvas.addEventListener('pointermove', async (e) => {
+        if (canvas.hasPointerCapture(e.pointerId)) {
+            await handlers.pointerMove(e.offsetX, e.offsetY, canvas.clientWidth, canvas.clientHeight);
+        }
+    });
+    const pointerUp = async (e: PointerEvent) => {
+        canvas.releasePointerCapture(e.pointerId);
+        await handlers.pointerUp();
+    };
+    canvas.addEventListener('pointerup', pointerUp);
+    canvas.addEventListener('pointercancel', pointerUp);
+
     // create offscreen canvas, pass to worker, and start WASM sim+render loop in worker
     const offscreenCanvas = canvas.transferControlToOffscreen();
-    const numParticles = await handlers.init(Comlink.transfer(offscreenCanvas, [offscreenCanvas]), Comlink.proxy(stats), Comlink.proxy(simPanel), useDarkMode);
+    const numParticles = await handlers.init(Comlink.transfer(offscreenCanvas, [offscreenCanvas]), Comlink.proxy(stats), Comlink.proxy(simPanel), Comlink.proxy(diagnosticPanels), useDarkMode);
     setInfo(numParticles);
+    await syncParameters();
 })();
\ No newline at end of file
@@ -1,25 +1,161 @@
 use std::borrow::Cow;
 
-use glam::Vec2;
 use glium::{glutin, index, uniform, Surface, VertexFormat};
-use glutin::event::{ElementState, Event, KeyboardInput, StartCause, VirtualKeyCode, WindowEvent};
-use log::info;
+use glutin::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, MouseButton, StartCause, VirtualKeyCode,
+    WindowEvent,
+};
+use log::{error, info, warn};
+use solver::color::{dye_colors, Attribute, ColorMapping, Colormap, Range};
+use solver::{Interaction, InteractionKind, Shape};
 
 const DAM_PARTICLES: usize = 75 * 75;
 const BLOCK_PARTICLES: usize = 500;
 const MAX_PARTICLES: usize = solver::MAX_PARTICLES;
 const POINT_SIZE: f32 = 7.5;
+const MIN_TIME_SCALE: solver::Real = 1.0 / 16.0;
+const MAX_TIME_SCALE: solver::Real = 8.0;
+/// Radius of the middle mouse button eraser in simulation units
+const ERASER_RADIUS: solver::Real = 0.5;
+
+const FLUID_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+
+const USAGE: &str = "usage: native [--threads <n>] [--scene <file>] \
+    [--color-by <quantity>] [--colormap <name>] [--range <min>:<max>]";
+
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    /// Caps the solver's cores, defaults to the global rayon pool
+    threads: Option<usize>,
+    scene: Option<solver::Scene>,
+    color_mapping: Option<ColorMapping>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let (mut color_by, mut colormap, mut range) = (None, Colormap::Viridis, Range::Auto);
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {arg}, {USAGE}"))
+            };
+            match arg.as_str() {
+                "--threads" | "-j" => {
+                    let value = value()?;
+                    let n = value
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid thread count {value}: {e}"))?;
+                    parsed.threads = Some(n);
+                }
+                "--scene" => {
+                    let path = value()?;
+                    let scene = solver::Scene::load(&path)
+                        .map_err(|e| format!("Failed to load scene {path}: {e}"))?;
+                    parsed.scene = Some(scene);
+                }
+                "--color-by" => {
+                    let value = value()?;
+                    color_by = Some(
+                        Attribute::from_name(&value)
+                            .ok_or_else(|| format!("Unknown quantity {value}, {USAGE}"))?,
+                    );
+                }
+                "--colormap" => {
+                    let value = value()?;
+                    colormap = Colormap::from_name(&value)
+                        .ok_or_else(|| format!("Unknown colormap {value}, {USAGE}"))?;
+                }
+                "--range" => {
+                    let value = value()?;
+                    range = Range::parse(&value)
+                        .ok_or_else(|| format!("Invalid range {value}, {USAGE}"))?;
+                }
+                _ => return Err(format!("Unknown argument {arg}, {USAGE}")),
+            }
+        }
+        parsed.color_mapping = color_by.map(|attribute| ColorMapping {
+            attribute,
+            colormap,
+            range,
+        });
+        Ok(parsed)
+    }
+}
+
+/// Loads the scene if one was given, else the default dam break.
+fn init(sim: &mut solver::State, scene: Option<&solver::Scene>) -> Result<(), String> {
+    match scene {
+        Some(scene) => {
+            sim.load_scene(scene).map_err(|e| e.to_string())?;
+            info!("Loaded scene with {} particles", sim.particles.len());
+        }
+        None => {
+            sim.clear();
+            sim.init_dam_break(DAM_PARTICLES);
+            info!(
+                "Initialized dam break with {} particles",
+                sim.particles.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Cycles through no coloring and coloring by each attribute in turn.
+fn next_attribute(mapping: Option<ColorMapping>) -> Option<ColorMapping> {
+    let Some(mapping) = mapping else {
+        return Some(ColorMapping::new(Attribute::ALL[0]));
+    };
+    let i = Attribute::ALL
+        .iter()
+        .position(|a| *a == mapping.attribute)?;
+    Attribute::ALL.get(i + 1).map(|&attribute| ColorMapping {
+        attribute,
+        // fixed ranges rarely fit another quantity
+        range: Range::Auto,
+        ..mapping
+    })
+}
+
+fn ortho_matrix(sim: &solver::State) -> [[f32; 4]; 4] {
+    let (width, height) = sim.view_size();
+    cgmath::ortho(0.0, width, 0.0, height, 0.0, 1.0).into()
+}
+
+/// Converts a cursor position in window pixels to simulation units.
+fn cursor_to_world(
+    sim: &solver::State,
+    cursor: glutin::dpi::PhysicalPosition<f64>,
+    window: glutin::dpi::PhysicalSize<u32>,
+) -> solver::Vec2 {
+    let (width, height) = sim.view_size();
+    let x = cursor.x / f64::from(window.width) * f64::from(width);
+    let y = (1.0 - cursor.y / f64::from(window.height)) * f64::from(height);
+    solver::Vec2::new(x as solver::Real, y as solver::Real)
+}
 
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<(), String> {
     env_logger::init();
 
-    let mut sim = solver::State::new();
-    sim.init_dam_break(DAM_PARTICLES);
-    info!(
-        "Initialized dam break with {} particles",
-        sim.particles.len()
-    );
+    let args = Args::parse()?;
+    let mut sim = match args.threads {
+        Some(n) => solver::State::with_num_threads(n)
+            .map_err(|e| format!("Failed to create solver thread pool: {e}"))?,
+        None => solver::State::new(),
+    };
+    info!("Solver running on {} threads", sim.num_threads());
+    sim.set_safeguard(Some(solver::Safeguard::default()));
+    init(&mut sim, args.scene.as_ref())?;
 
     let event_loop = glutin::event_loop::EventLoop::new();
     let size: glutin::dpi::LogicalSize<u32> = (solver::WINDOW_WIDTH, solver::WINDOW_HEIGHT).into();
@@ -35,42 +171,54 @@ fn main() -> Result<(), String> {
         #version 140
         uniform mat4 u_matrix;
         in vec2 in_position;
+        in vec3 in_color;
+        out vec3 v_color;
         void main() {
+            v_color = in_color;
             gl_Position = u_matrix * vec4(in_position, 0.0, 1.0);
         }
     "#;
     let fragment_shader_src = r#"
         #version 140
+        in vec3 v_color;
         out vec4 out_color;
         void main() {
-            out_color = vec4(0.2, 0.6, 1.0, 1.0);
+            out_color = vec4(v_color, 1.0);
         }
     "#;
     let program =
         glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None)
             .map_err(|e| format!("Failed to parse vertex shader source: {e}"))?;
-    let ortho_matrix: [[f32; 4]; 4] =
-        cgmath::ortho(0.0, solver::VIEW_WIDTH, 0.0, solver::VIEW_HEIGHT, 0.0, 1.0).into();
+    // the domain only changes when a scene is loaded, which happens before this point
     let uniforms = uniform! {
-        u_matrix: ortho_matrix
+        u_matrix: ortho_matrix(&sim)
     };
     let indices = index::NoIndices(index::PrimitiveType::Points);
 
     // preallocate vertex buffer
-    let mut vertex_data = vec![Vec2::ZERO; MAX_PARTICLES];
-    let bindings: VertexFormat = Cow::Owned(vec![(
-        Cow::Borrowed("in_position"),
-        0,
-        0,
-        glium::vertex::AttributeType::F32F32,
-        false,
-    )]);
+    let mut vertex_data = vec![Vertex::default(); MAX_PARTICLES];
+    let bindings: VertexFormat = Cow::Owned(vec![
+        (
+            Cow::Borrowed("in_position"),
+            0,
+            0,
+            glium::vertex::AttributeType::F32F32,
+            false,
+        ),
+        (
+            Cow::Borrowed("in_color"),
+            2 * std::mem::size_of::<f32>(),
+            0,
+            glium::vertex::AttributeType::F32F32F32,
+            false,
+        ),
+    ]);
     let vertex_buffer = unsafe {
         glium::VertexBuffer::new_raw_dynamic(
             &display,
             &vertex_data,
             bindings,
-            2 * std::mem::size_of::<f32>(),
+            std::mem::size_of::<Vertex>(),
         )
         .map_err(|e| format!("Failed to create vertex buffer: {e}"))?
     };
@@ -80,6 +228,12 @@ fn main() -> Result<(), String> {
         ..Default::default()
     };
 
+    let mut color_mapping = args.color_mapping;
+    let mut colors = Vec::with_capacity(MAX_PARTICLES);
+    let mut cursor = glutin::dpi::PhysicalPosition::new(0.0, 0.0);
+    let mut modifiers = ModifiersState::empty();
+    let mut erasing = false;
+
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent { event, .. } => match event {
@@ -98,41 +252,148 @@ fn main() -> Result<(), String> {
                 } => match (virtual_code, state) {
                     (VirtualKeyCode::R, ElementState::Pressed) => {
                         vertex_buffer.invalidate();
-                        sim.clear();
                         info!("Cleared simulation");
-                        sim.init_dam_break(DAM_PARTICLES);
+                        if let Err(e) = init(&mut sim, args.scene.as_ref()) {
+                            error!("Failed to reset simulation: {e}");
+                        }
                     }
                     (VirtualKeyCode::Space, ElementState::Pressed) => {
-                        if sim.particles.len() + BLOCK_PARTICLES < MAX_PARTICLES {
-                            sim.init_block(BLOCK_PARTICLES);
-                            info!(
+                        match sim.try_init_block(BLOCK_PARTICLES) {
+                            Ok(()) => info!(
                                 "Initialized block of {BLOCK_PARTICLES} particles, new total {}",
                                 sim.particles.len()
-                            );
-                        } else {
-                            info!("Max particles reached");
+                            ),
+                            Err(e) => info!("Cannot add block: {e}"),
+                        }
+                    }
+                    (VirtualKeyCode::C, ElementState::Pressed) => {
+                        color_mapping = next_attribute(color_mapping);
+                        match color_mapping {
+                            Some(mapping) => info!("Coloring by {}", mapping.attribute.name()),
+                            None => info!("Coloring disabled"),
+                        }
+                    }
+                    (VirtualKeyCode::M, ElementState::Pressed) => {
+                        if let Some(mapping) = color_mapping.as_mut() {
+                            let i = Colormap::ALL.iter().position(|c| *c == mapping.colormap);
+                            let next = i.map_or(0, |i| (i + 1) % Colormap::ALL.len());
+                            mapping.colormap = Colormap::ALL[next];
+                            info!("Using colormap {}", mapping.colormap.name());
                         }
                     }
+                    (VirtualKeyCode::P, ElementState::Pressed) => {
+                        sim.set_paused(!sim.paused());
+                        info!(
+                            "{} at t = {:.3}",
+                            if sim.paused() { "Paused" } else { "Resumed" },
+                            sim.time()
+                        );
+                    }
+                    (VirtualKeyCode::G, ElementState::Pressed) => {
+                        let safeguard = match sim.safeguard() {
+                            Some(_) => None,
+                            None => Some(solver::Safeguard::default()),
+                        };
+                        info!(
+                            "Safeguard {}",
+                            if safeguard.is_some() {
+                                "enabled"
+                            } else {
+                                "disabled"
+                            }
+                        );
+                        sim.set_safeguard(safeguard);
+                    }
+                    (VirtualKeyCode::Period, ElementState::Pressed) => sim.step_frame(),
+                    (VirtualKeyCode::Comma, ElementState::Pressed) => sim.step_substep(),
+                    (VirtualKeyCode::Minus | VirtualKeyCode::Equals, ElementState::Pressed) => {
+                        let factor = if virtual_code == VirtualKeyCode::Minus {
+                            0.5
+                        } else {
+                            2.0
+                        };
+                        sim.set_time_scale(
+                            (sim.time_scale() * factor).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE),
+                        );
+                        info!("Time scale {}", sim.time_scale());
+                    }
                     (VirtualKeyCode::Escape, ElementState::Pressed) => {
                         *control_flow = glutin::event_loop::ControlFlow::Exit;
                         return;
                     }
                     _ => (),
                 },
+                WindowEvent::ModifiersChanged(state) => {
+                    modifiers = state;
+                    return;
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    cursor = position;
+                    return;
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Middle,
+                    ..
+                } => {
+                    erasing = state == ElementState::Pressed;
+                    return;
+                }
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let kind = match button {
+                        MouseButton::Left => InteractionKind::Drag,
+                        MouseButton::Right if modifiers.shift() => InteractionKind::Repel,
+                        MouseButton::Right => InteractionKind::Attract,
+                        _ => return,
+                    };
+                    let interaction = (state == ElementState::Pressed).then(|| {
+                        let size = display.gl_window().window().inner_size();
+                        Interaction::new(kind, cursor_to_world(&sim, cursor, size))
+                    });
+                    sim.set_interaction(interaction);
+                    return;
+                }
                 _ => return,
             },
-            Event::NewEvents(cause) => match cause {
-                StartCause::Init | StartCause::Poll => (),
-                _ => return,
-            },
+            Event::NewEvents(StartCause::Init | StartCause::Poll) => (),
             _ => return,
         }
 
-        sim.update();
+        if sim.interaction().is_some() {
+            let size = display.gl_window().window().inner_size();
+            sim.move_interaction(cursor_to_world(&sim, cursor, size));
+        }
+        if erasing {
+            let size = display.gl_window().window().inner_size();
+            sim.remove_particles_in(&Shape::Circle {
+                center: cursor_to_world(&sim, cursor, size),
+                radius: ERASER_RADIUS,
+            });
+        }
+        if let Err(e) = sim.try_update() {
+            // pause on divergence so that the broken state can be inspected
+            error!("Simulation diverged, pausing (press R to reset): {e}");
+            sim.set_paused(true);
+        }
+        match sim.last_recovery() {
+            Some(recovery) if recovery.recovered => warn!("{recovery}"),
+            Some(recovery) => error!("{recovery}"),
+            None => (),
+        }
 
         // draw
+        colors.clear();
+        match color_mapping {
+            Some(mapping) => colors.extend(mapping.colors(&sim).0),
+            None => colors.extend(dye_colors(&sim, FLUID_COLOR)),
+        }
         vertex_data.clear();
-        sim.particles.iter().for_each(|p| vertex_data.push(p.x));
+        sim.particles.iter().zip(&colors).for_each(|(p, color)| {
+            vertex_data.push(Vertex {
+                position: solver::to_f32(p.x).to_array(),
+                color: *color,
+            });
+        });
         vertex_buffer
             .slice(0..vertex_data.len())
             .unwrap()
@@ -0,0 +1,487 @@
+//! Versioned binary checkpoints of the complete solver state.
+//!
+//! Layout (all values little-endian):
+//! - magic `b"PCISPHCK"`, format version `u32`, scalar size in bytes `u8`
+//! - simulated time, then the time scale, the fraction of a solver step carried
+//!   over to the next frame and whether updates are paused as `u8`
+//! - [`Parameters`] in declaration order
+//! - domain size, then whether it is periodic along x as `u8`
+//! - boundary count `u32` followed by one `(nx, ny, offset)` triple per boundary
+//! - obstacle count `u32` followed by the shapes, then emitter count `u32`
+//!   followed by the emitters including their progress, temperature and
+//!   concentrations
+//! - whether heat transfer is enabled as `u8`, then the [`Thermal`] settings in
+//!   declaration order with the source count `u32` before the sources
+//! - scalar channel count `u32` followed by the [`ScalarChannel`] settings in
+//!   declaration order with the injector count `u32` before the injectors
+//! - next particle id `u32`
+//! - particle count `u64` followed by `x, xlast, v, m, p, pv, d, id, t, c` per
+//!   particle, with all [`MAX_SCALARS`] concentrations `c`
+//!
+//! Optional scalars are stored as NaN when absent. Strings and lists of scalars
+//! are stored as a `u32` length followed by the elements, colors as three `f32`.
+//!
+//! Grid and neighborhood data are rebuilt at the start of every solver step and
+//! are therefore not stored. Scalars are written at solver precision, so a
+//! checkpoint can only be loaded by a build with the same `f64` feature setting.
+
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+
+use crate::{
+    Emitter, HeatSource, Injector, Neighbor, Parameters, Particle, Real, ScalarChannel, Shape,
+    State, Thermal, Vec2, Vec3, WallTemperatures, MAX_PARTICLES, MAX_SCALARS, NUM_NEIGHBORS,
+};
+
+const MAGIC: &[u8; 8] = b"PCISPHCK";
+const VERSION: u32 = 1;
+
+const SHAPE_RECTANGLE: u8 = 0;
+const SHAPE_CIRCLE: u8 = 1;
+const SHAPE_POLYGON: u8 = 2;
+
+/// Upper bound for obstacle, emitter and polygon vertex counts, guards against
+/// huge allocations when reading corrupt data
+const MAX_ITEMS: usize = 1 << 20;
+
+impl State {
+    /// Writes the complete simulation state to `writer`.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing fails.
+    pub fn save_checkpoint<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = Writer(writer);
+        w.bytes(MAGIC)?;
+        w.u32(VERSION)?;
+        w.bytes(&[size_of::<Real>() as u8])?;
+
+        w.real(self.time)?;
+        w.real(self.time_scale)?;
+        w.real(self.pending_steps)?;
+        w.bytes(&[u8::from(self.paused)])?;
+        let params = &self.params;
+        w.vec2(params.gravity)?;
+        w.real(params.frame_dt)?;
+        w.u64(params.substeps as u64)?;
+        w.real(params.rest_density)?;
+        w.real(params.stiffness)?;
+        w.real(params.stiffness_near)?;
+        w.real(params.surface_tension)?;
+        w.real(params.linear_viscosity)?;
+        w.real(params.quadratic_viscosity)?;
+        w.real(params.vorticity_confinement)?;
+        w.real(params.xsph)?;
+
+        w.vec2(self.domain)?;
+        w.bytes(&[u8::from(self.periodic_x)])?;
+        w.u32(self.boundaries.len() as u32)?;
+        for b in &self.boundaries {
+            w.vec3(*b)?;
+        }
+        w.u32(self.obstacles.len() as u32)?;
+        for obstacle in &self.obstacles {
+            w.shape(obstacle)?;
+        }
+        w.u32(self.emitters.len() as u32)?;
+        for emitter in &self.emitters {
+            w.shape(&emitter.shape)?;
+            w.vec2(emitter.velocity)?;
+            w.real(emitter.rate)?;
+            w.real(emitter.start)?;
+            w.optional_real(emitter.stop)?;
+            w.real(emitter.pending)?;
+            w.u64(emitter.emitted)?;
+            w.optional_real(emitter.temperature)?;
+            w.reals(&emitter.concentrations)?;
+        }
+        w.bytes(&[u8::from(self.thermal.is_some())])?;
+        if let Some(thermal) = &self.thermal {
+            w.real(thermal.reference_temperature)?;
+            w.real(thermal.diffusivity)?;
+            w.real(thermal.expansion)?;
+            w.real(thermal.viscosity_falloff)?;
+            for temperature in thermal.walls.to_array() {
+                w.optional_real(temperature)?;
+            }
+            w.real(thermal.wall_conductance)?;
+            w.u32(thermal.sources.len() as u32)?;
+            for source in &thermal.sources {
+                w.shape(&source.shape)?;
+                w.real(source.temperature)?;
+                w.real(source.rate)?;
+            }
+        }
+        w.u32(self.scalars.len() as u32)?;
+        for channel in &self.scalars {
+            w.string(&channel.name)?;
+            w.real(channel.diffusivity)?;
+            w.color(channel.color)?;
+            w.u32(channel.injectors.len() as u32)?;
+            for injector in &channel.injectors {
+                w.shape(&injector.shape)?;
+                w.real(injector.concentration)?;
+                w.real(injector.rate)?;
+            }
+        }
+
+        w.u32(self.next_id)?;
+        w.u64(self.particles.len() as u64)?;
+        for p in &self.particles {
+            w.vec2(p.x)?;
+            w.vec2(p.xlast)?;
+            w.vec2(p.v)?;
+            w.real(p.m)?;
+            w.real(p.p)?;
+            w.real(p.pv)?;
+            w.real(p.d)?;
+            w.u32(p.id)?;
+            w.real(p.t)?;
+            p.c.iter().try_for_each(|c| w.real(*c))?;
+        }
+        w.0.flush()
+    }
+
+    /// Replaces the simulation state with the checkpoint read from `reader`. The
+    /// thread pool of `self` is kept. Continuing from a loaded checkpoint gives
+    /// the same results as an uninterrupted run.
+    ///
+    /// # Errors
+    /// Will return `Err` if reading fails or the data is not a compatible checkpoint,
+    /// in which case `self` is left unchanged.
+    pub fn load_checkpoint<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        let mut r = Reader(reader);
+        let mut magic = [0; MAGIC.len()];
+        r.bytes(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a solver checkpoint"));
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported checkpoint version {version}, expected {VERSION}"
+            )));
+        }
+        let mut scalar_size = [0];
+        r.bytes(&mut scalar_size)?;
+        if usize::from(scalar_size[0]) != size_of::<Real>() {
+            return Err(invalid_data(format!(
+                "checkpoint uses {}-byte scalars but solver uses {}-byte scalars",
+                scalar_size[0],
+                size_of::<Real>()
+            )));
+        }
+
+        let time = r.real()?;
+        let time_scale = r.real()?;
+        let pending_steps = r.real()?;
+        let paused = r.flag()?;
+        let params = Parameters {
+            gravity: r.vec2()?,
+            frame_dt: r.real()?,
+            substeps: r.len(usize::MAX)?,
+            rest_density: r.real()?,
+            stiffness: r.real()?,
+            stiffness_near: r.real()?,
+            surface_tension: r.real()?,
+            linear_viscosity: r.real()?,
+            quadratic_viscosity: r.real()?,
+            vorticity_confinement: r.real()?,
+            xsph: r.real()?,
+        };
+        if params.substeps == 0 {
+            return Err(invalid_data("checkpoint has zero substeps"));
+        }
+
+        let domain = r.vec2()?;
+        crate::scene::validate_domain(domain).map_err(invalid_data)?;
+        let periodic_x = r.flag()?;
+        let mut boundaries = self.boundaries;
+        if r.u32()? as usize != boundaries.len() {
+            return Err(invalid_data("unexpected number of boundaries"));
+        }
+        for b in &mut boundaries {
+            *b = r.vec3()?;
+        }
+        let mut obstacles = Vec::new();
+        for _ in 0..r.u32_len(MAX_ITEMS)? {
+            obstacles.push(r.shape()?);
+        }
+        let mut emitters = Vec::new();
+        for _ in 0..r.u32_len(MAX_ITEMS)? {
+            let mut emitter = Emitter::new(r.shape()?, r.vec2()?, r.real()?);
+            emitter.start = r.real()?;
+            emitter.stop = r.optional_real()?;
+            emitter.pending = r.real()?;
+            emitter.emitted = r.u64()?;
+            emitter.temperature = r.optional_real()?;
+            emitter.concentrations = r.reals()?;
+            emitters.push(emitter);
+        }
+        let mut thermal = None;
+        if r.flag()? {
+            let mut settings = Thermal {
+                reference_temperature: r.real()?,
+                diffusivity: r.real()?,
+                expansion: r.real()?,
+                viscosity_falloff: r.real()?,
+                walls: WallTemperatures {
+                    left: r.optional_real()?,
+                    bottom: r.optional_real()?,
+                    right: r.optional_real()?,
+                    top: r.optional_real()?,
+                },
+                wall_conductance: r.real()?,
+                sources: Vec::new(),
+            };
+            for _ in 0..r.u32_len(MAX_ITEMS)? {
+                settings.sources.push(HeatSource {
+                    shape: r.shape()?,
+                    temperature: r.real()?,
+                    rate: r.real()?,
+                });
+            }
+            thermal = Some(settings);
+        }
+        let mut scalars = Vec::new();
+        for _ in 0..r.u32_len(MAX_SCALARS)? {
+            let mut channel = ScalarChannel {
+                name: r.string()?,
+                diffusivity: r.real()?,
+                color: r.color()?,
+                injectors: Vec::new(),
+            };
+            for _ in 0..r.u32_len(MAX_ITEMS)? {
+                channel.injectors.push(Injector {
+                    shape: r.shape()?,
+                    concentration: r.real()?,
+                    rate: r.real()?,
+                });
+            }
+            scalars.push(channel);
+        }
+
+        let next_id = r.u32()?;
+        let num_particles = r.len(MAX_PARTICLES)?;
+        let mut particles = Vec::with_capacity(MAX_PARTICLES);
+        for _ in 0..num_particles {
+            let mut p = Particle {
+                x: r.vec2()?,
+                xlast: r.vec2()?,
+                v: r.vec2()?,
+                m: r.real()?,
+                p: r.real()?,
+                pv: r.real()?,
+                d: r.real()?,
+                id: r.u32()?,
+                t: r.real()?,
+                ..Particle::default()
+            };
+            for c in &mut p.c {
+                *c = r.real()?;
+            }
+            particles.push(p);
+        }
+
+        self.clear();
+        self.set_domain(domain);
+        self.set_periodic_x(periodic_x);
+        self.particles = particles;
+        self.particles_initial
+            .resize(num_particles, Particle::default());
+        self.neighborhoods.resize_with(num_particles, || {
+            Vec::<Neighbor>::with_capacity(NUM_NEIGHBORS)
+        });
+        self.boundaries = boundaries;
+        self.obstacles = obstacles;
+        self.emitters = emitters;
+        self.thermal = thermal;
+        self.scalars = scalars;
+        self.params = params;
+        self.time = time;
+        self.time_scale = time_scale;
+        self.pending_steps = pending_steps;
+        self.paused = paused;
+        self.next_id = next_id;
+        Ok(())
+    }
+}
+
+fn invalid_data<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+struct Writer<W>(W);
+
+impl<W: Write> Writer<W> {
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.0.write_all(bytes)
+    }
+
+    fn u32(&mut self, v: u32) -> io::Result<()> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn u64(&mut self, v: u64) -> io::Result<()> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn real(&mut self, v: Real) -> io::Result<()> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    fn optional_real(&mut self, v: Option<Real>) -> io::Result<()> {
+        self.real(v.unwrap_or(Real::NAN))
+    }
+
+    fn reals(&mut self, v: &[Real]) -> io::Result<()> {
+        self.u32(v.len() as u32)?;
+        v.iter().try_for_each(|v| self.real(*v))
+    }
+
+    fn string(&mut self, s: &str) -> io::Result<()> {
+        self.u32(s.len() as u32)?;
+        self.bytes(s.as_bytes())
+    }
+
+    fn color(&mut self, color: [f32; 3]) -> io::Result<()> {
+        color.iter().try_for_each(|c| self.bytes(&c.to_le_bytes()))
+    }
+
+    fn vec2(&mut self, v: Vec2) -> io::Result<()> {
+        self.real(v.x)?;
+        self.real(v.y)
+    }
+
+    fn vec3(&mut self, v: Vec3) -> io::Result<()> {
+        self.real(v.x)?;
+        self.real(v.y)?;
+        self.real(v.z)
+    }
+
+    fn shape(&mut self, shape: &Shape) -> io::Result<()> {
+        match shape {
+            Shape::Rectangle { min, max } => {
+                self.bytes(&[SHAPE_RECTANGLE])?;
+                self.vec2(*min)?;
+                self.vec2(*max)
+            }
+            Shape::Circle { center, radius } => {
+                self.bytes(&[SHAPE_CIRCLE])?;
+                self.vec2(*center)?;
+                self.real(*radius)
+            }
+            Shape::Polygon { vertices } => {
+                self.bytes(&[SHAPE_POLYGON])?;
+                self.u32(vertices.len() as u32)?;
+                vertices.iter().try_for_each(|v| self.vec2(*v))
+            }
+        }
+    }
+}
+
+struct Reader<R>(R);
+
+impl<R: Read> Reader<R> {
+    fn bytes(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.0.read_exact(buf)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; size_of::<u32>()];
+        self.bytes(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; size_of::<u64>()];
+        self.bytes(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn flag(&mut self) -> io::Result<bool> {
+        let mut flag = [0];
+        self.bytes(&mut flag)?;
+        Ok(flag[0] != 0)
+    }
+
+    /// Reads a `u64` length and checks it against `max`.
+    fn len(&mut self, max: usize) -> io::Result<usize> {
+        let len = self.u64()?;
+        usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= max)
+            .ok_or_else(|| invalid_data(format!("length {len} exceeds maximum {max}")))
+    }
+
+    fn real(&mut self) -> io::Result<Real> {
+        let mut buf = [0; size_of::<Real>()];
+        self.bytes(&mut buf)?;
+        Ok(Real::from_le_bytes(buf))
+    }
+
+    fn optional_real(&mut self) -> io::Result<Option<Real>> {
+        Ok(Some(self.real()?).filter(|v| !v.is_nan()))
+    }
+
+    fn reals(&mut self) -> io::Result<Vec<Real>> {
+        (0..self.u32_len(MAX_ITEMS)?).map(|_| self.real()).collect()
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let mut buf = vec![0; self.u32_len(MAX_ITEMS)?];
+        self.bytes(&mut buf)?;
+        String::from_utf8(buf).map_err(invalid_data)
+    }
+
+    fn color(&mut self) -> io::Result<[f32; 3]> {
+        let mut color = [0.0; 3];
+        for c in &mut color {
+            let mut buf = [0; size_of::<f32>()];
+            self.bytes(&mut buf)?;
+            *c = f32::from_le_bytes(buf);
+        }
+        Ok(color)
+    }
+
+    fn vec2(&mut self) -> io::Result<Vec2> {
+        Ok(Vec2::new(self.real()?, self.real()?))
+    }
+
+    fn vec3(&mut self) -> io::Result<Vec3> {
+        Ok(Vec3::new(self.real()?, self.real()?, self.real()?))
+    }
+
+    /// Reads a `u32` count and checks it against `max`.
+    fn u32_len(&mut self, max: usize) -> io::Result<usize> {
+        let len = self.u32()? as usize;
+        if len > max {
+            return Err(invalid_data(format!("length {len} exceeds maximum {max}")));
+        }
+        Ok(len)
+    }
+
+    fn shape(&mut self) -> io::Result<Shape> {
+        let mut tag = [0];
+        self.bytes(&mut tag)?;
+        match tag[0] {
+            SHAPE_RECTANGLE => Ok(Shape::Rectangle {
+                min: self.vec2()?,
+                max: self.vec2()?,
+            }),
+            SHAPE_CIRCLE => Ok(Shape::Circle {
+                center: self.vec2()?,
+                radius: self.real()?,
+            }),
+            SHAPE_POLYGON => {
+                let vertices = (0..self.u32_len(MAX_ITEMS)?)
+                    .map(|_| self.vec2())
+                    .collect::<io::Result<_>>()?;
+                Ok(Shape::Polygon { vertices })
+            }
+            tag => Err(invalid_data(format!("unknown shape tag {tag}"))),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Mapping of per-particle quantities to colors, shared by all renderers.
+
+use crate::State;
+
+/// Per-particle quantity that can be visualized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribute {
+    Speed,
+    Pressure,
+    Density,
+    /// Relative deviation of the density from the rest density
+    DensityError,
+    Vorticity,
+    NeighborCount,
+    Temperature,
+}
+
+impl Attribute {
+    pub const ALL: [Self; 7] = [
+        Self::Speed,
+        Self::Pressure,
+        Self::Density,
+        Self::DensityError,
+        Self::Vorticity,
+        Self::NeighborCount,
+        Self::Temperature,
+    ];
+
+    /// Lowercase name, e.g. `"density_error"`.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Speed => "speed",
+            Self::Pressure => "pressure",
+            Self::Density => "density",
+            Self::DensityError => "density_error",
+            Self::Vorticity => "vorticity",
+            Self::NeighborCount => "neighbors",
+            Self::Temperature => "temperature",
+        }
+    }
+
+    /// Parses a name as returned by [`Attribute::name`].
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// Values of all particles of `state`, in particle order.
+    #[must_use]
+    pub fn values(self, state: &State) -> Vec<f32> {
+        let particles = &state.particles;
+        let rest_density = state.params().rest_density;
+        match self {
+            Self::Speed => particles.iter().map(|p| p.v.length() as f32).collect(),
+            Self::Pressure => particles.iter().map(|p| p.p as f32).collect(),
+            Self::Density => particles.iter().map(|p| p.d as f32).collect(),
+            Self::DensityError => particles
+                .iter()
+                .map(|p| (p.d / (p.m * rest_density) - 1.0) as f32)
+                .collect(),
+            Self::Vorticity => state.vorticity().into_iter().map(|w| w as f32).collect(),
+            Self::NeighborCount => state
+                .neighbor_counts()
+                .into_iter()
+                .map(|n| n as f32)
+                .collect(),
+            Self::Temperature => particles.iter().map(|p| p.t as f32).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// Perceptually uniform dark blue to yellow map from matplotlib
+    Viridis,
+    /// Diverging blue to red map by Moreland, suited to signed quantities
+    Coolwarm,
+}
+
+const VIRIDIS: [[f32; 3]; 9] = [
+    [0.267, 0.005, 0.329],
+    [0.278, 0.175, 0.483],
+    [0.231, 0.322, 0.545],
+    [0.173, 0.447, 0.557],
+    [0.129, 0.569, 0.549],
+    [0.157, 0.682, 0.502],
+    [0.369, 0.788, 0.384],
+    [0.678, 0.863, 0.188],
+    [0.992, 0.906, 0.145],
+];
+
+const COOLWARM: [[f32; 3]; 9] = [
+    [0.230, 0.299, 0.754],
+    [0.348, 0.466, 0.888],
+    [0.484, 0.622, 0.975],
+    [0.619, 0.744, 0.999],
+    [0.865, 0.865, 0.865],
+    [0.958, 0.754, 0.652],
+    [0.958, 0.603, 0.482],
+    [0.871, 0.392, 0.314],
+    [0.706, 0.016, 0.150],
+];
+
+impl Colormap {
+    pub const ALL: [Self; 2] = [Self::Viridis, Self::Coolwarm];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Viridis => "viridis",
+            Self::Coolwarm => "coolwarm",
+        }
+    }
+
+    /// Parses a name as returned by [`Colormap::name`].
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// RGB color at `t`, which is clamped to `[0, 1]`.
+    #[must_use]
+    pub fn sample(self, t: f32) -> [f32; 3] {
+        let table = match self {
+            Self::Viridis => &VIRIDIS,
+            Self::Coolwarm => &COOLWARM,
+        };
+        let x = t.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+        let i = (x as usize).min(table.len() - 2);
+        let f = x - i as f32;
+        let (a, b) = (table[i], table[i + 1]);
+        [0, 1, 2].map(|c| a[c] + f * (b[c] - a[c]))
+    }
+}
+
+/// Value range that is mapped onto the colormap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Range {
+    /// Minimum and maximum of the current frame
+    Auto,
+    Fixed(f32, f32),
+}
+
+impl Range {
+    /// Parses `auto` or a fixed range given as `<min>:<max>`.
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        if value == "auto" {
+            return Some(Self::Auto);
+        }
+        let (min, max) = value.split_once(':')?;
+        Some(Self::Fixed(min.parse().ok()?, max.parse().ok()?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorMapping {
+    pub attribute: Attribute,
+    pub colormap: Colormap,
+    pub range: Range,
+}
+
+impl ColorMapping {
+    /// Maps `attribute` with viridis over the per-frame range.
+    #[must_use]
+    pub fn new(attribute: Attribute) -> Self {
+        Self {
+            attribute,
+            colormap: Colormap::Viridis,
+            range: Range::Auto,
+        }
+    }
+
+    /// Resolves [`Range::Auto`] for the given attribute values.
+    #[must_use]
+    pub fn range_of(&self, values: &[f32]) -> (f32, f32) {
+        match self.range {
+            Range::Fixed(min, max) => (min, max),
+            Range::Auto => values
+                .iter()
+                .fold(None, |range, &v| match range {
+                    None => Some((v, v)),
+                    Some((min, max)) => Some((f32::min(min, v), f32::max(max, v))),
+                })
+                .unwrap_or((0.0, 1.0)),
+        }
+    }
+
+    /// Colors of all particles of `state`, in particle order, together with the
+    /// value range they were mapped from.
+    #[must_use]
+    pub fn colors(&self, state: &State) -> (Vec<[f32; 3]>, (f32, f32)) {
+        let values = self.attribute.values(state);
+        let (min, max) = self.range_of(&values);
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+        let colors = values
+            .iter()
+            .map(|v| self.colormap.sample((v - min) * scale))
+            .collect();
+        (colors, (min, max))
+    }
+}
+
+/// Colors of all particles of `state`, in particle order, showing the scalar
+/// channels on top of `base`. Each channel blends its color over the previous
+/// ones by its concentration clamped to `[0, 1]`, so particles without any
+/// scalar keep `base`.
+#[must_use]
+pub fn dye_colors(state: &State, base: [f32; 3]) -> Vec<[f32; 3]> {
+    let scalars = state.scalars();
+    state
+        .particles
+        .iter()
+        .map(|p| {
+            scalars
+                .iter()
+                .enumerate()
+                .fold(base, |color, (k, channel)| {
+                    let a = (p.concentration(k) as f32).clamp(0.0, 1.0);
+                    [0, 1, 2].map(|i| color[i] + a * (channel.color[i] - color[i]))
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+//! Summary statistics of the simulation, for monitoring stability and accuracy.
+
+use crate::{minimum_image, neighbor_cells, Real, State, EPS2, H2, NUM_NEIGHBORS, PARTICLE_RADIUS};
+
+/// Statistics of the particles after the last solver step, see [`State::diagnostics`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    pub time: Real,
+    pub particles: usize,
+    /// Sum of `m v² / 2` over all particles
+    pub kinetic_energy: Real,
+    /// Gravitational potential energy relative to the origin of the domain
+    pub potential_energy: Real,
+    pub max_speed: Real,
+    pub mean_speed: Real,
+    /// Mean of the relative deviation `ρ / ρ₀ - 1` from the rest density, which
+    /// is negative where the fluid is underdense, e.g. at the free surface
+    pub mean_density_error: Real,
+    /// Largest relative compression above the rest density
+    pub max_density_error: Real,
+    /// Number of particles by neighbor count, indexed from `0` to `NUM_NEIGHBORS`
+    pub neighbor_histogram: Vec<usize>,
+    /// Neighbors within the smoothing radius that were left out because a
+    /// particle's neighborhood was full
+    pub truncated_neighbors: usize,
+    /// Particles in contact with the domain boundaries or an obstacle
+    pub boundary_particles: usize,
+}
+
+impl State {
+    /// Computes [`Diagnostics`] of the current state. This visits every neighbor
+    /// pair again, so it is meant to be called occasionally rather than every step.
+    #[must_use]
+    pub fn diagnostics(&self) -> Diagnostics {
+        let n = self.particles.len();
+        let gravity = self.params.gravity;
+        let rest_density = self.params.rest_density;
+        let periodic = self.periodic_x;
+
+        let mut kinetic_energy = 0.0;
+        let mut potential_energy = 0.0;
+        let mut max_speed: Real = 0.0;
+        let mut speed_sum = 0.0;
+        let mut boundary_particles = 0;
+        for p in &self.particles {
+            let speed = p.v.length();
+            kinetic_energy += 0.5 * p.m * speed * speed;
+            potential_energy -= p.m * gravity.dot(p.x);
+            max_speed = max_speed.max(speed);
+            speed_sum += speed;
+            let touches_boundary = self
+                .boundaries
+                .iter()
+                .filter(|b| !periodic || b.x == 0.0)
+                .any(|b| p.x.x * b.x + p.x.y * b.y - b.z < PARTICLE_RADIUS)
+                || self
+                    .obstacles
+                    .iter()
+                    .any(|o| o.signed_distance(p.x).0 < PARTICLE_RADIUS);
+            if touches_boundary {
+                boundary_particles += 1;
+            }
+        }
+
+        // particles emitted since the last step have no density yet
+        let (error_sum, max_density_error, weighed) = self
+            .particles
+            .iter()
+            .filter(|p| p.d > 0.0)
+            .map(|p| p.d / (p.m * rest_density) - 1.0)
+            .fold(
+                (0.0, 0.0, 0),
+                |(sum, max, count): (Real, Real, usize), e| (sum + e, max.max(e), count + 1),
+            );
+
+        let mut neighbor_histogram = vec![0; NUM_NEIGHBORS + 1];
+        for count in self.neighbor_counts() {
+            neighbor_histogram[count] += 1;
+        }
+
+        Diagnostics {
+            time: self.time,
+            particles: n,
+            kinetic_energy,
+            potential_energy,
+            max_speed,
+            mean_speed: if n > 0 { speed_sum / n as Real } else { 0.0 },
+            mean_density_error: if weighed > 0 {
+                error_sum / weighed as Real
+            } else {
+                0.0
+            },
+            max_density_error,
+            neighbor_histogram,
+            truncated_neighbors: self.truncated_neighbors(),
+            boundary_particles,
+        }
+    }
+
+    /// Counts the neighbors dropped by the last solver step. The grid and
+    /// `particles_initial` still hold the positions the neighborhoods were built from.
+    fn truncated_neighbors(&self) -> usize {
+        if self.grid_width == 0 {
+            return 0;
+        }
+        let grid_width = self.grid_width as u32;
+        let period = self.period_x();
+        self.neighborhoods
+            .iter()
+            .enumerate()
+            .filter(|(_, neighbors)| neighbors.len() == NUM_NEIGHBORS)
+            .filter_map(|(i, _)| self.particles_initial.get(i))
+            .map(|pi| {
+                let count = neighbor_cells(pi.grid_index, grid_width, period > 0.0)
+                    .filter_map(|cell| self.grid.get(cell))
+                    .flatten()
+                    .filter_map(|j| self.particles_initial.get(*j))
+                    .filter(|pj| {
+                        let dx = minimum_image(pj.x - pi.x, period);
+                        (EPS2..=H2).contains(&dx.length_squared())
+                    })
+                    .count();
+                count.saturating_sub(NUM_NEIGHBORS)
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,274 @@
+//! Errors reported by the fallible `try_*` variants of the solver API.
+
+use std::fmt;
+
+use crate::{
+    Parameters, Real, Shape, State, Vec2, H, MAX_PARTICLES, MAX_SCALARS, PARTICLE_SPACING,
+};
+
+/// Largest ratio of the lattice points within the bounds of a shape to
+/// [`MAX_PARTICLES`] for which the lattice is built, a circle needs about 1.3
+const MAX_LATTICE_FACTOR: usize = 4;
+
+/// Distance beyond the walls at which [`State::check`] reports a particle as
+/// escaped, splashes hitting a wall overshoot it by up to about four `H`
+const OUT_OF_DOMAIN_MARGIN: Real = 5.0 * H;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolverError {
+    /// Adding `requested` particles would exceed [`MAX_PARTICLES`]
+    CapacityExceeded {
+        requested: usize,
+        available: usize,
+    },
+    /// A particle's position or velocity is NaN or infinite
+    NonFinite {
+        index: usize,
+    },
+    /// A particle escaped beyond the domain boundaries, see [`State::check`]
+    OutOfDomain {
+        index: usize,
+        position: Vec2,
+    },
+    /// A particle moved too far in a single solver step, see [`crate::Safeguard`]
+    VelocitySpike {
+        index: usize,
+        speed: Real,
+    },
+    /// A particle was compressed far beyond the rest density, see [`crate::Safeguard`]
+    DensityBlowUp {
+        index: usize,
+        density_error: Real,
+    },
+    InvalidParameter {
+        name: &'static str,
+        value: Real,
+    },
+    /// More than [`MAX_SCALARS`] scalar channels were requested
+    TooManyScalars {
+        requested: usize,
+    },
+    /// A scalar channel name is not an ASCII identifier or clashes with a
+    /// built-in export array, see [`State::set_scalars`]
+    InvalidScalarName {
+        name: String,
+    },
+    /// Two scalar channels share a name
+    DuplicateScalarName {
+        name: String,
+    },
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityExceeded {
+                requested,
+                available,
+            } => write!(
+                f,
+                "cannot add {requested} particles, only {available} of {MAX_PARTICLES} are free"
+            ),
+            Self::NonFinite { index } => {
+                write!(f, "particle {index} has a non-finite position or velocity")
+            }
+            Self::OutOfDomain { index, position } => {
+                write!(f, "particle {index} escaped the domain to {position}")
+            }
+            Self::VelocitySpike { index, speed } => {
+                write!(f, "particle {index} spiked to speed {speed}")
+            }
+            Self::DensityBlowUp {
+                index,
+                density_error,
+            } => write!(
+                f,
+                "particle {index} is compressed {:.0}% beyond the rest density",
+                100.0 * density_error
+            ),
+            Self::InvalidParameter { name, value } => {
+                write!(f, "invalid value {value} for parameter {name}")
+            }
+            Self::TooManyScalars { requested } => write!(
+                f,
+                "cannot track {requested} scalar channels, at most {MAX_SCALARS} are supported"
+            ),
+            Self::InvalidScalarName { name } => write!(
+                f,
+                "scalar name {name:?} must be an ASCII identifier other than a built-in array name"
+            ),
+            Self::DuplicateScalarName { name } => {
+                write!(f, "scalar name {name:?} is used more than once")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SolverError {}
+
+impl Parameters {
+    /// Checks that all parameters are finite, that `frame_dt`, `substeps` and
+    /// `rest_density` are positive and that the remaining coefficients are not negative.
+    ///
+    /// # Errors
+    /// Will return `Err` naming the first offending parameter.
+    pub fn validate(&self) -> Result<(), SolverError> {
+        let invalid = |name, value| Err(SolverError::InvalidParameter { name, value });
+        // comparisons are written so that NaN values are rejected as well
+        for (name, value) in [("gravity_x", self.gravity.x), ("gravity_y", self.gravity.y)] {
+            if !value.is_finite() {
+                return invalid(name, value);
+            }
+        }
+        if self.substeps == 0 {
+            return invalid("substeps", 0.0);
+        }
+        for (name, value) in [
+            ("frame_dt", self.frame_dt),
+            ("rest_density", self.rest_density),
+        ] {
+            if !(value > 0.0 && value.is_finite()) {
+                return invalid(name, value);
+            }
+        }
+        for (name, value) in [
+            ("stiffness", self.stiffness),
+            ("stiffness_near", self.stiffness_near),
+            ("surface_tension", self.surface_tension),
+            ("linear_viscosity", self.linear_viscosity),
+            ("quadratic_viscosity", self.quadratic_viscosity),
+            ("vorticity_confinement", self.vorticity_confinement),
+            ("xsph", self.xsph),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                return invalid(name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl State {
+    /// Like [`State::set_params`], but keeps the current parameters if `params`
+    /// are invalid.
+    ///
+    /// # Errors
+    /// Will return `Err` if [`Parameters::validate`] fails.
+    pub fn try_set_params(&mut self, params: Parameters) -> Result<(), SolverError> {
+        params.validate()?;
+        self.set_params(params);
+        Ok(())
+    }
+
+    /// Like [`State::fill_shape`], but adds nothing instead of a partial fill if
+    /// the shape holds more particles than are free.
+    ///
+    /// # Errors
+    /// Will return `Err` if the particles would exceed [`MAX_PARTICLES`].
+    pub fn try_fill_shape(&mut self, shape: &Shape, velocity: Vec2) -> Result<usize, SolverError> {
+        let points = self.fitting_lattice(shape)?;
+        Ok(self.fill_points(&points, velocity))
+    }
+
+    /// Like [`State::init_dam_break`], checking the particle capacity first.
+    ///
+    /// # Errors
+    /// Will return `Err` if the particles would exceed [`MAX_PARTICLES`].
+    pub fn try_init_dam_break(&mut self, num_particles: usize) -> Result<(), SolverError> {
+        self.reserve(square_count(num_particles))?;
+        self.init_dam_break(num_particles);
+        Ok(())
+    }
+
+    /// Like [`State::init_block`], checking the particle capacity first.
+    ///
+    /// # Errors
+    /// Will return `Err` if the particles would exceed [`MAX_PARTICLES`].
+    pub fn try_init_block(&mut self, num_particles: usize) -> Result<(), SolverError> {
+        self.reserve(square_count(num_particles))?;
+        self.init_block(num_particles);
+        Ok(())
+    }
+
+    /// Like [`State::update`], but validates the parameters before and the particles
+    /// after advancing, see [`State::check`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the parameters are invalid, in which case the state is
+    /// not advanced, or if the update left the particles in an invalid state. Does
+    /// nothing while paused, like [`State::update`].
+    pub fn try_update(&mut self) -> Result<(), SolverError> {
+        if self.paused {
+            return Ok(());
+        }
+        self.params.validate()?;
+        self.update();
+        self.check()
+    }
+
+    /// Checks that all particles have finite positions and velocities and did not
+    /// escape the domain by more than five smoothing radii, which the soft
+    /// boundaries would otherwise hide by clamping them into the outermost grid cells.
+    ///
+    /// # Errors
+    /// Will return `Err` for the first offending particle.
+    pub fn check(&self) -> Result<(), SolverError> {
+        let (min, max) = (
+            Vec2::splat(-OUT_OF_DOMAIN_MARGIN),
+            self.domain + OUT_OF_DOMAIN_MARGIN,
+        );
+        for (index, p) in self.particles.iter().enumerate() {
+            if !(p.x.is_finite() && p.v.is_finite()) {
+                return Err(SolverError::NonFinite { index });
+            }
+            // periodic domains wrap x, so only y can escape
+            let inside_x = self.periodic_x || (min.x..=max.x).contains(&p.x.x);
+            if !(inside_x && (min.y..=max.y).contains(&p.x.y)) {
+                return Err(SolverError::OutOfDomain {
+                    index,
+                    position: p.x,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Lattice of `shape` if its particles fit. Shapes whose bounds hold far more
+    /// lattice points than [`MAX_PARTICLES`] are rejected before the lattice is
+    /// built, with their bound as the requested count.
+    pub(crate) fn fitting_lattice(&self, shape: &Shape) -> Result<Vec<Vec2>, SolverError> {
+        let bound = shape.lattice_bound(PARTICLE_SPACING);
+        if bound.is_nan() || bound > (MAX_LATTICE_FACTOR * MAX_PARTICLES) as Real {
+            return Err(SolverError::CapacityExceeded {
+                // saturates for infinite bounds
+                requested: if bound.is_nan() {
+                    usize::MAX
+                } else {
+                    bound as usize
+                },
+                available: MAX_PARTICLES.saturating_sub(self.particles.len()),
+            });
+        }
+        let points = shape.lattice(PARTICLE_SPACING);
+        self.reserve(points.len())?;
+        Ok(points)
+    }
+
+    fn reserve(&self, requested: usize) -> Result<(), SolverError> {
+        let available = MAX_PARTICLES.saturating_sub(self.particles.len());
+        if requested > available {
+            return Err(SolverError::CapacityExceeded {
+                requested,
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Number of particles placed for a square of `num_particles`, which is rounded
+/// down to a square number.
+fn square_count(num_particles: usize) -> usize {
+    let side = (num_particles as Real).sqrt() as usize;
+    side * side
+}
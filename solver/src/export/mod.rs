@@ -0,0 +1,9 @@
+//! Writers for particle data in formats understood by external post-processing tools.
+
+pub mod npy;
+pub mod png;
+pub mod vtk;
+
+pub use self::png::{Image, RenderOptions};
+pub use npy::{Npz, NpzRecorder};
+pub use vtk::VtkSeries;
@@ -0,0 +1,359 @@
+//! `NumPy` `.npy` and `.npz` export, loadable with `numpy.load` without a custom parser.
+//!
+//! Single frames are written with [`State::write_npz`], whole runs are collected
+//! with an [`NpzRecorder`] and stacked along a leading frame axis. Archives are
+//! stored uncompressed, matching `numpy.savez`.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::{Real, State};
+
+/// Element types that can be stored in an `.npy` array.
+pub trait Element: Copy {
+    /// `NumPy` dtype string, e.g. `<f4`.
+    const DESCR: &'static str;
+
+    fn extend_le_bytes(self, bytes: &mut Vec<u8>);
+}
+
+impl Element for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn extend_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Element for f64 {
+    const DESCR: &'static str = "<f8";
+
+    fn extend_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Element for u32 {
+    const DESCR: &'static str = "<u4";
+
+    fn extend_le_bytes(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Encodes `data` as an `.npy` file (format version 1.0) with the given C-order `shape`.
+///
+/// # Panics
+/// Panics if the number of elements does not match `shape`.
+#[must_use]
+pub fn encode_npy<T: Element>(shape: &[usize], data: &[T]) -> Vec<u8> {
+    assert_eq!(
+        shape.iter().product::<usize>(),
+        data.len(),
+        "array data does not match shape {shape:?}"
+    );
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}",
+        T::DESCR
+    );
+    // magic, version and header length take 10 bytes, the data must start 64-byte aligned
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.extend(std::iter::repeat_n(' ', padding % 64));
+    header.push('\n');
+
+    let mut bytes = Vec::with_capacity(10 + header.len() + std::mem::size_of_val(data));
+    bytes.extend_from_slice(b"\x93NUMPY\x01\x00");
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    for v in data {
+        v.extend_le_bytes(&mut bytes);
+    }
+    bytes
+}
+
+/// Writes `data` as an `.npy` file with the given C-order `shape`.
+///
+/// # Errors
+/// Will return `Err` if writing fails.
+pub fn write_npy<T: Element, W: Write>(
+    mut writer: W,
+    shape: &[usize],
+    data: &[T],
+) -> io::Result<()> {
+    writer.write_all(&encode_npy(shape, data))?;
+    writer.flush()
+}
+
+/// Uncompressed zip archive of named `.npy` arrays, equivalent to `numpy.savez`.
+#[derive(Debug, Default)]
+pub struct Npz {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Npz {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an array that `numpy.load` exposes under `name`.
+    pub fn add<T: Element>(&mut self, name: &str, shape: &[usize], data: &[T]) -> &mut Self {
+        self.entries
+            .push((format!("{name}.npy"), encode_npy(shape, data)));
+        self
+    }
+
+    /// Writes the archive.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing fails or the archive exceeds the 4 GiB zip limit.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "npz archive exceeds 4 GiB");
+        let mut central = Vec::new();
+        let mut offset = 0u32;
+        for (name, data) in &self.entries {
+            let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+            let crc = crc32(data);
+            let mut local = Vec::with_capacity(30 + name.len());
+            local.extend_from_slice(&0x0403_4b50u32.to_le_bytes()); // local file header
+            push_entry_fields(&mut local, name, crc, size);
+            local.extend_from_slice(name.as_bytes());
+            writer.write_all(&local)?;
+            writer.write_all(data)?;
+
+            central.extend_from_slice(&0x0201_4b50u32.to_le_bytes()); // central directory header
+            central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            push_entry_fields(&mut central, name, crc, size);
+            central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+
+            offset = u32::try_from(local.len() + data.len())
+                .ok()
+                .and_then(|len| offset.checked_add(len))
+                .ok_or_else(too_large)?;
+        }
+        let num_entries = self.entries.len() as u16;
+        writer.write_all(&central)?;
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50u32.to_le_bytes()); // end of central directory
+        end.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        end.extend_from_slice(&0u16.to_le_bytes()); // disk with central directory
+        end.extend_from_slice(&num_entries.to_le_bytes());
+        end.extend_from_slice(&num_entries.to_le_bytes());
+        end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        end.extend_from_slice(&offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        writer.write_all(&end)?;
+        writer.flush()
+    }
+}
+
+/// Fields shared by local and central zip headers for a stored (uncompressed) entry.
+fn push_entry_fields(bytes: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+    bytes.extend_from_slice(&20u16.to_le_bytes()); // version needed to extract
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // flags
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // compression: stored
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // modification time
+    bytes.extend_from_slice(&0x0021u16.to_le_bytes()); // modification date, 1980-01-01
+    bytes.extend_from_slice(&crc.to_le_bytes());
+    bytes.extend_from_slice(&size.to_le_bytes()); // compressed size
+    bytes.extend_from_slice(&size.to_le_bytes()); // uncompressed size
+    bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+impl State {
+    /// Writes the current frame as an `.npz` archive with arrays `time` (scalar),
+    /// `ids` `(n,)`, `positions` `(n, 2)`, `velocities` `(n, 2)`, `pressures` `(n,)`,
+    /// `near_pressures` `(n,)`, `densities` `(n,)`, `temperatures` `(n,)`,
+    /// `vorticities` `(n,)` and `concentrations` `(n, k)` of the `k` scalar channels
+    /// in their order.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing fails.
+    pub fn write_npz<W: Write>(&self, writer: W) -> io::Result<()> {
+        let n = self.particles.len();
+        let ids: Vec<u32> = self.particles.iter().map(|p| p.id).collect();
+        let frame = Frame::new(self);
+        Npz::new()
+            .add("time", &[], &[self.time])
+            .add("ids", &[n], &ids)
+            .add("positions", &[n, 2], &frame.positions)
+            .add("velocities", &[n, 2], &frame.velocities)
+            .add("pressures", &[n], &frame.pressures)
+            .add("near_pressures", &[n], &frame.near_pressures)
+            .add("densities", &[n], &frame.densities)
+            .add("temperatures", &[n], &frame.temperatures)
+            .add("vorticities", &[n], &frame.vorticities)
+            .add(
+                "concentrations",
+                &[n, frame.channels],
+                &frame.concentrations,
+            )
+            .write(writer)
+    }
+}
+
+/// Per-particle arrays of a single frame in `NumPy` layout.
+#[derive(Debug, Default)]
+struct Frame {
+    positions: Vec<Real>,
+    velocities: Vec<Real>,
+    pressures: Vec<Real>,
+    near_pressures: Vec<Real>,
+    densities: Vec<Real>,
+    temperatures: Vec<Real>,
+    vorticities: Vec<Real>,
+    concentrations: Vec<Real>,
+    /// Number of scalar channels in `concentrations`
+    channels: usize,
+}
+
+impl Frame {
+    fn new(state: &State) -> Self {
+        let mut frame = Self::default();
+        frame.extend(state);
+        frame
+    }
+
+    fn extend(&mut self, state: &State) {
+        self.channels = state.scalars.len();
+        self.vorticities.extend(state.vorticity());
+        for p in &state.particles {
+            self.positions.extend_from_slice(&[p.x.x, p.x.y]);
+            self.velocities.extend_from_slice(&[p.v.x, p.v.y]);
+            self.pressures.push(p.p);
+            self.near_pressures.push(p.pv);
+            self.densities.push(p.d);
+            self.temperatures.push(p.t);
+            self.concentrations.extend_from_slice(&p.c[..self.channels]);
+        }
+    }
+}
+
+/// Collects frames of a run in memory and writes them as one `.npz` archive with
+/// arrays `times` `(frames,)`, `ids` `(n,)`, `positions` `(frames, n, 2)`,
+/// `velocities` `(frames, n, 2)`, `pressures` `(frames, n)`, `near_pressures`
+/// `(frames, n)`, `densities` `(frames, n)`, `temperatures` `(frames, n)`,
+/// `vorticities` `(frames, n)` and `concentrations` `(frames, n, k)`.
+///
+/// `ids` lists every particle of the run in order of first appearance, so
+/// particles that are emitted or removed are NaN in the frames they are missing
+/// from. All frames must have the same scalar channels.
+#[derive(Debug, Default)]
+pub struct NpzRecorder {
+    times: Vec<Real>,
+    /// Particle ids of each frame in the order of its arrays in `frames`
+    frame_ids: Vec<Vec<u32>>,
+    frames: Frame,
+}
+
+impl NpzRecorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn num_frames(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Appends the current frame of `state`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the scalar channels differ from the previously recorded frames.
+    pub fn record(&mut self, state: &State) -> io::Result<()> {
+        if !self.times.is_empty() && state.scalars.len() != self.frames.channels {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "scalar channels changed between recorded frames",
+            ));
+        }
+        self.times.push(state.time);
+        self.frame_ids
+            .push(state.particles.iter().map(|p| p.id).collect());
+        self.frames.extend(state);
+        Ok(())
+    }
+
+    /// Writes all recorded frames.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing fails.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut ids = Vec::new();
+        let mut columns = HashMap::new();
+        for id in self.frame_ids.iter().flatten() {
+            columns.entry(*id).or_insert_with(|| {
+                ids.push(*id);
+                ids.len() - 1
+            });
+        }
+        let (f, n, k) = (self.times.len(), ids.len(), self.frames.channels);
+        // scatters the per-frame values of each particle into its column
+        let pad = |values: &[Real], width: usize| {
+            let mut padded = vec![Real::NAN; f * n * width];
+            let particles = self
+                .frame_ids
+                .iter()
+                .enumerate()
+                .flat_map(|(frame, ids)| ids.iter().map(move |id| (frame, id)));
+            for ((frame, id), v) in particles.zip(values.chunks_exact(width.max(1))) {
+                let start = (frame * n + columns[id]) * width;
+                padded[start..start + width].copy_from_slice(&v[..width]);
+            }
+            padded
+        };
+        Npz::new()
+            .add("times", &[f], &self.times)
+            .add("ids", &[n], &ids)
+            .add("positions", &[f, n, 2], &pad(&self.frames.positions, 2))
+            .add("velocities", &[f, n, 2], &pad(&self.frames.velocities, 2))
+            .add("pressures", &[f, n], &pad(&self.frames.pressures, 1))
+            .add(
+                "near_pressures",
+                &[f, n],
+                &pad(&self.frames.near_pressures, 1),
+            )
+            .add("densities", &[f, n], &pad(&self.frames.densities, 1))
+            .add("temperatures", &[f, n], &pad(&self.frames.temperatures, 1))
+            .add("vorticities", &[f, n], &pad(&self.frames.vorticities, 1))
+            .add(
+                "concentrations",
+                &[f, n, k],
+                &pad(&self.frames.concentrations, k),
+            )
+            .write(writer)
+    }
+}
@@ -0,0 +1,207 @@
+//! CPU rasterizer rendering frames to PNG images, for machines without a GL context.
+//!
+//! Frames use the same orthographic view as the interactive frontends: `(0, 0)`
+//! maps to the bottom-left corner and [`State::view_size`] to the top-right.
+
+use std::io::{self, Write};
+
+use crate::color::{dye_colors, ColorMapping};
+use crate::{to_f32, Real, State, Vec2, VIEW_WIDTH, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+/// Diameter in pixels of the points drawn by the native frontend
+const NATIVE_POINT_SIZE: f32 = 7.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Disc radius in simulation units
+    pub particle_radius: f32,
+    pub background: [f32; 3],
+    pub particle_color: [f32; 3],
+    /// Colors particles by a per-particle quantity instead of `particle_color`
+    /// and the scalar channels
+    pub color_mapping: Option<ColorMapping>,
+    /// Fill color of obstacles, which are not drawn if `None`
+    pub obstacle_color: Option<[f32; 3]>,
+}
+
+impl Default for RenderOptions {
+    /// Matches the look of the native frontend at its window size.
+    fn default() -> Self {
+        Self {
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            particle_radius: 0.5 * NATIVE_POINT_SIZE * VIEW_WIDTH / WINDOW_WIDTH as f32,
+            background: [0.9, 0.9, 0.9],
+            particle_color: [0.2, 0.6, 1.0],
+            color_mapping: None,
+            obstacle_color: Some([0.4, 0.4, 0.4]),
+        }
+    }
+}
+
+/// RGB image with color components in `[0, 1]`, stored row by row from the top.
+#[derive(Debug, Clone)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Image {
+    #[must_use]
+    pub fn new(width: u32, height: u32, background: [f32; 3]) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![background; width as usize * height as usize],
+        }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Color of the pixel in column `x` and row `y`, counted from the top-left.
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> [f32; 3] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// 8-bit RGB pixel data, row by row from the top.
+    #[must_use]
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flatten()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    }
+
+    /// Writes the image as an 8-bit RGB PNG.
+    ///
+    /// # Errors
+    /// Will return `Err` if encoding or writing fails.
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.to_rgb8()))
+            .map_err(io::Error::other)
+    }
+
+    /// Blends `color` over the pixel with the given coverage in `[0, 1]`.
+    fn blend(&mut self, x: usize, y: usize, color: [f32; 3], coverage: f32) {
+        let pixel = &mut self.pixels[y * self.width as usize + x];
+        for (c, target) in pixel.iter_mut().zip(color) {
+            *c += coverage * (target - *c);
+        }
+    }
+}
+
+/// Maps simulation coordinates to continuous pixel coordinates with `y` pointing down.
+struct View {
+    scale: glam::Vec2,
+    height: f32,
+}
+
+impl View {
+    fn new(state: &State, width: u32, height: u32) -> Self {
+        let (view_width, view_height) = state.view_size();
+        Self {
+            scale: glam::Vec2::new(width as f32 / view_width, height as f32 / view_height),
+            height: view_height,
+        }
+    }
+
+    fn to_pixel(&self, p: glam::Vec2) -> glam::Vec2 {
+        glam::Vec2::new(p.x, self.height - p.y) * self.scale
+    }
+
+    fn to_world(&self, p: glam::Vec2) -> glam::Vec2 {
+        let p = p / self.scale;
+        glam::Vec2::new(p.x, self.height - p.y)
+    }
+
+    /// Pixels per simulation unit, averaged over both axes for stretched views.
+    fn pixels_per_unit(&self) -> f32 {
+        (self.scale.x * self.scale.y).sqrt()
+    }
+}
+
+/// Range of pixel indices whose centers may lie within `[min, max]`.
+fn pixel_range(min: f32, max: f32, len: u32) -> std::ops::Range<usize> {
+    let start = (min - 0.5).floor().max(0.0) as usize;
+    let end = ((max + 0.5).ceil().max(0.0) as usize).min(len as usize);
+    start..end
+}
+
+impl State {
+    /// Rasterizes the current frame on the CPU, drawing obstacles and then
+    /// particles as anti-aliased discs in particle order.
+    #[must_use]
+    pub fn render(&self, options: &RenderOptions) -> Image {
+        let mut image = Image::new(options.width, options.height, options.background);
+        let view = View::new(self, options.width, options.height);
+        let pixels_per_unit = view.pixels_per_unit();
+
+        if let Some(color) = options.obstacle_color {
+            for obstacle in &self.obstacles {
+                let (min, max) = obstacle.bounds();
+                let (min, max) = (view.to_pixel(to_f32(min)), view.to_pixel(to_f32(max)));
+                // y is flipped, so the corners swap rows
+                for y in pixel_range(max.y - 1.0, min.y + 1.0, options.height) {
+                    for x in pixel_range(min.x - 1.0, max.x + 1.0, options.width) {
+                        let center = glam::Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                        let world = view.to_world(center);
+                        let (distance, _) = obstacle
+                            .signed_distance(Vec2::new(Real::from(world.x), Real::from(world.y)));
+                        let coverage = (0.5 - distance as f32 * pixels_per_unit).clamp(0.0, 1.0);
+                        if coverage > 0.0 {
+                            image.blend(x, y, color, coverage);
+                        }
+                    }
+                }
+            }
+        }
+
+        let colors = match options.color_mapping {
+            Some(mapping) => mapping.colors(self).0,
+            None => dye_colors(self, options.particle_color),
+        };
+        let radius = options.particle_radius * pixels_per_unit;
+        // discs smaller than a pixel fade out instead of vanishing
+        let opacity = (2.0 * radius).min(1.0);
+        for (i, particle) in self.particles.iter().enumerate() {
+            let color = colors[i];
+            let center = view.to_pixel(to_f32(particle.x));
+            for y in pixel_range(center.y - radius, center.y + radius, options.height) {
+                for x in pixel_range(center.x - radius, center.x + radius, options.width) {
+                    let pixel = glam::Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let coverage = (radius + 0.5 - pixel.distance(center)).clamp(0.0, 1.0);
+                    if coverage > 0.0 {
+                        image.blend(x, y, color, coverage * opacity);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /// Renders the current frame with [`State::render`] and writes it as a PNG.
+    ///
+    /// # Errors
+    /// Will return `Err` if encoding or writing fails.
+    pub fn write_png<W: Write>(&self, writer: W, options: &RenderOptions) -> io::Result<()> {
+        self.render(options).write_png(writer)
+    }
+}
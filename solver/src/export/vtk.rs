@@ -0,0 +1,231 @@
+//! VTK XML export for `ParaView`: one `.vtu` unstructured grid of vertex cells per
+//! frame plus a `.pvd` collection indexing the frames by simulated time.
+//!
+//! Array data is stored raw in an appended section, which keeps frames compact
+//! while remaining readable by any VTK XML reader.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
+use crate::{Real, State};
+
+const VTK_VERTEX: u8 = 1;
+
+impl State {
+    /// Writes the current particles as a VTK XML unstructured grid (`.vtu`) with
+    /// position, velocity, pressure, near-pressure, density, mass, temperature,
+    /// vorticity and one array per scalar channel, named like the channel.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing fails.
+    pub fn write_vtu<W: Write>(&self, writer: W) -> io::Result<()> {
+        let particles = &self.particles;
+        let mut arrays = Appended::default();
+        let velocity = arrays.push_reals(particles.iter().flat_map(|p| [p.v.x, p.v.y, 0.0]));
+        let pressure = arrays.push_reals(particles.iter().map(|p| p.p));
+        let near_pressure = arrays.push_reals(particles.iter().map(|p| p.pv));
+        let density = arrays.push_reals(particles.iter().map(|p| p.d));
+        let mass = arrays.push_reals(particles.iter().map(|p| p.m));
+        let temperature = arrays.push_reals(particles.iter().map(|p| p.t));
+        let vorticity = arrays.push_reals(self.vorticity().into_iter());
+        let scalars: Vec<_> = (self.scalars.iter().enumerate())
+            .map(|(k, channel)| {
+                let offset = arrays.push_reals(particles.iter().map(|p| p.c[k]));
+                (channel.name.as_str(), offset)
+            })
+            .collect();
+        let points = arrays.push_reals(particles.iter().flat_map(|p| [p.x.x, p.x.y, 0.0]));
+        let connectivity =
+            arrays.push_bytes((0..particles.len() as i64).flat_map(i64::to_le_bytes));
+        let offsets = arrays.push_bytes((1..=particles.len() as i64).flat_map(i64::to_le_bytes));
+        let types = arrays.push_bytes(std::iter::repeat_n(VTK_VERTEX, particles.len()));
+
+        let real = real_type_name();
+        let mut scalar_arrays = String::new();
+        for (name, offset) in scalars {
+            let _ = write!(
+                scalar_arrays,
+                r#"
+        <DataArray type="{real}" Name="{name}" format="appended" offset="{offset}"/>"#
+            );
+        }
+        let n = particles.len();
+        let mut w = BufWriter::new(writer);
+        write!(
+            w,
+            r#"<?xml version="1.0"?>
+<VTKFile type="UnstructuredGrid" version="1.0" byte_order="LittleEndian" header_type="UInt64">
+  <UnstructuredGrid>
+    <Piece NumberOfPoints="{n}" NumberOfCells="{n}">
+      <PointData Scalars="pressure" Vectors="velocity">
+        <DataArray type="{real}" Name="velocity" NumberOfComponents="3" format="appended" offset="{velocity}"/>
+        <DataArray type="{real}" Name="pressure" format="appended" offset="{pressure}"/>
+        <DataArray type="{real}" Name="near_pressure" format="appended" offset="{near_pressure}"/>
+        <DataArray type="{real}" Name="density" format="appended" offset="{density}"/>
+        <DataArray type="{real}" Name="mass" format="appended" offset="{mass}"/>
+        <DataArray type="{real}" Name="temperature" format="appended" offset="{temperature}"/>
+        <DataArray type="{real}" Name="vorticity" format="appended" offset="{vorticity}"/>{scalar_arrays}
+      </PointData>
+      <Points>
+        <DataArray type="{real}" Name="position" NumberOfComponents="3" format="appended" offset="{points}"/>
+      </Points>
+      <Cells>
+        <DataArray type="Int64" Name="connectivity" format="appended" offset="{connectivity}"/>
+        <DataArray type="Int64" Name="offsets" format="appended" offset="{offsets}"/>
+        <DataArray type="UInt8" Name="types" format="appended" offset="{types}"/>
+      </Cells>
+    </Piece>
+  </UnstructuredGrid>
+  <AppendedData encoding="raw">
+   _"#
+        )?;
+        w.write_all(&arrays.data)?;
+        write!(
+            w,
+            r#"
+  </AppendedData>
+</VTKFile>
+"#
+        )?;
+        w.flush()
+    }
+}
+
+/// Writes a sequence of frames to a directory as `<prefix>_<frame>.vtu` files
+/// together with a `<prefix>.pvd` time series that `ParaView` opens directly.
+/// The index is rewritten after every frame so it stays valid if a run is
+/// interrupted.
+#[derive(Debug)]
+pub struct VtkSeries {
+    dir: PathBuf,
+    prefix: String,
+    frames: Vec<(Real, String)>,
+}
+
+impl VtkSeries {
+    /// Creates the output directory if necessary.
+    ///
+    /// # Errors
+    /// Will return `Err` if the directory cannot be created.
+    pub fn new(dir: impl Into<PathBuf>, prefix: &str) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            prefix: prefix.to_owned(),
+            frames: Vec::new(),
+        })
+    }
+
+    /// Continues an existing series, e.g. after restarting from a checkpoint taken
+    /// at `time`. Frames of the existing index at or after `time` are dropped, as
+    /// the restarted run writes them again. A missing index starts a new series.
+    ///
+    /// # Errors
+    /// Will return `Err` if the directory cannot be created or the index cannot be read.
+    pub fn resume(dir: impl Into<PathBuf>, prefix: &str, time: Real) -> io::Result<Self> {
+        let mut series = Self::new(dir, prefix)?;
+        let index = match fs::read_to_string(series.index_path()) {
+            Ok(index) => index,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(series),
+            Err(e) => return Err(e),
+        };
+        // the index is only ever written by `write_index`, one data set per line
+        for line in index
+            .lines()
+            .filter(|l| l.trim_start().starts_with("<DataSet"))
+        {
+            let frame_time = attribute(line, "timestep").and_then(|t| t.parse::<Real>().ok());
+            if let (Some(frame_time), Some(file_name)) = (frame_time, attribute(line, "file")) {
+                if frame_time < time {
+                    series.frames.push((frame_time, file_name.to_owned()));
+                }
+            }
+        }
+        Ok(series)
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    #[must_use]
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Writes `state` as frame number `frame`, stamped with the simulated time, and
+    /// updates the `.pvd` index. Returns the path of the written frame.
+    ///
+    /// # Errors
+    /// Will return `Err` if writing either file fails.
+    pub fn write_frame(&mut self, frame: usize, state: &State) -> io::Result<PathBuf> {
+        let file_name = format!("{}_{frame:06}.vtu", self.prefix);
+        let path = self.dir.join(&file_name);
+        state.write_vtu(File::create(&path)?)?;
+        self.frames.push((state.time(), file_name));
+        self.write_index()?;
+        Ok(path)
+    }
+
+    fn write_index(&self) -> io::Result<()> {
+        let mut index = String::from(
+            "<?xml version=\"1.0\"?>\n\
+             <VTKFile type=\"Collection\" version=\"0.1\" byte_order=\"LittleEndian\">\n  \
+             <Collection>\n",
+        );
+        for (time, file_name) in &self.frames {
+            let _ = writeln!(
+                index,
+                "    <DataSet timestep=\"{time}\" group=\"\" part=\"0\" file=\"{file_name}\"/>"
+            );
+        }
+        index.push_str("  </Collection>\n</VTKFile>\n");
+        fs::write(self.index_path(), index)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.pvd", self.prefix))
+    }
+}
+
+/// Value of the XML attribute `name="..."` in `line`.
+fn attribute<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let start = line.find(&format!(" {name}=\""))? + name.len() + 3;
+    let len = line[start..].find('"')?;
+    Some(&line[start..start + len])
+}
+
+fn real_type_name() -> &'static str {
+    if size_of::<Real>() == size_of::<f64>() {
+        "Float64"
+    } else {
+        "Float32"
+    }
+}
+
+/// Raw appended data section, each array is prefixed by its `UInt64` byte length.
+#[derive(Default)]
+struct Appended {
+    data: Vec<u8>,
+}
+
+impl Appended {
+    /// Appends an array and returns its offset within the section.
+    fn push_bytes(&mut self, bytes: impl Iterator<Item = u8>) -> usize {
+        let offset = self.data.len();
+        self.data.extend_from_slice(&0u64.to_le_bytes());
+        self.data.extend(bytes);
+        let len = (self.data.len() - offset - size_of::<u64>()) as u64;
+        self.data[offset..offset + size_of::<u64>()].copy_from_slice(&len.to_le_bytes());
+        offset
+    }
+
+    fn push_reals(&mut self, values: impl Iterator<Item = Real>) -> usize {
+        self.push_bytes(values.flat_map(Real::to_le_bytes))
+    }
+}
@@ -0,0 +1,144 @@
+//! Simple 2D shapes used to describe fluid regions, obstacles and emitters.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Real, Vec2};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Rectangle {
+        min: Vec2,
+        max: Vec2,
+    },
+    Circle {
+        center: Vec2,
+        radius: Real,
+    },
+    /// Simple polygon, vertices may be given in either winding order
+    Polygon {
+        vertices: Vec<Vec2>,
+    },
+}
+
+impl Shape {
+    #[must_use]
+    pub fn contains(&self, p: Vec2) -> bool {
+        match self {
+            Self::Rectangle { min, max } => {
+                p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+            }
+            Self::Circle { center, radius } => (p - *center).length_squared() <= radius * radius,
+            Self::Polygon { vertices } => {
+                // even-odd rule
+                let mut inside = false;
+                let mut j = vertices.len().wrapping_sub(1);
+                for (i, a) in vertices.iter().enumerate() {
+                    let b = vertices[j];
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x
+                    {
+                        inside = !inside;
+                    }
+                    j = i;
+                }
+                inside
+            }
+        }
+    }
+
+    /// Axis-aligned bounding box as `(min, max)`.
+    #[must_use]
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match self {
+            Self::Rectangle { min, max } => (*min, *max),
+            Self::Circle { center, radius } => (*center - *radius, *center + *radius),
+            Self::Polygon { vertices } => vertices.iter().fold(
+                (Vec2::splat(Real::INFINITY), Vec2::splat(Real::NEG_INFINITY)),
+                |(min, max), v| (min.min(*v), max.max(*v)),
+            ),
+        }
+    }
+
+    /// Signed distance from `p` to the outline, negative inside, together with the
+    /// outward unit normal of the closest point of the outline.
+    #[must_use]
+    pub fn signed_distance(&self, p: Vec2) -> (Real, Vec2) {
+        match self {
+            Self::Rectangle { min, max } => {
+                let center = (*min + *max) * 0.5;
+                let half = (*max - *min) * 0.5;
+                let q = (p - center).abs() - half;
+                let sign = (p - center).signum();
+                if q.x > 0.0 || q.y > 0.0 {
+                    let outside = q.max(Vec2::ZERO);
+                    let d = outside.length();
+                    (d, sign * outside / d)
+                } else if q.x > q.y {
+                    (q.x, Vec2::new(sign.x, 0.0))
+                } else {
+                    (q.y, Vec2::new(0.0, sign.y))
+                }
+            }
+            Self::Circle { center, radius } => {
+                let dx = p - *center;
+                let r = dx.length();
+                let normal = if r > 0.0 { dx / r } else { Vec2::Y };
+                (r - radius, normal)
+            }
+            Self::Polygon { vertices } => {
+                let mut closest = (Real::INFINITY, Vec2::Y);
+                let mut j = vertices.len().wrapping_sub(1);
+                for (i, a) in vertices.iter().enumerate() {
+                    let b = vertices[j];
+                    let ab = b - *a;
+                    let t =
+                        ((p - *a).dot(ab) / ab.length_squared().max(Real::EPSILON)).clamp(0.0, 1.0);
+                    let dx = p - (*a + t * ab);
+                    let d = dx.length();
+                    if d < closest.0 {
+                        let normal = if d > 0.0 {
+                            dx / d
+                        } else {
+                            ab.perp().normalize_or_zero()
+                        };
+                        closest = (d, normal);
+                    }
+                    j = i;
+                }
+                if self.contains(p) {
+                    (-closest.0, -closest.1)
+                } else {
+                    closest
+                }
+            }
+        }
+    }
+
+    /// Number of lattice points within the bounds of the shape, an upper bound
+    /// for the length of [`Shape::lattice`] that is cheap to compute.
+    #[must_use]
+    pub(crate) fn lattice_bound(&self, spacing: Real) -> Real {
+        let (min, max) = self.bounds();
+        let n = ((max - min) / spacing).floor() + 1.0;
+        n.x * n.y
+    }
+
+    /// Points of a regular lattice with the given `spacing` that lie inside the shape.
+    #[must_use]
+    pub fn lattice(&self, spacing: Real) -> Vec<Vec2> {
+        let (min, max) = self.bounds();
+        let nx = ((max.x - min.x) / spacing).floor() as usize + 1;
+        let ny = ((max.y - min.y) / spacing).floor() as usize + 1;
+        let mut points = Vec::new();
+        for j in 0..ny {
+            for i in 0..nx {
+                let p = min + Vec2::new(i as Real, j as Real) * spacing;
+                if self.contains(p) {
+                    points.push(p);
+                }
+            }
+        }
+        points
+    }
+}
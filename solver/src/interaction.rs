@@ -0,0 +1,95 @@
+//! User interaction forces, e.g. from a mouse or touch pointer.
+
+use crate::{Particle, Real, State, Vec2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InteractionKind {
+    /// Pulls particles towards the pointer
+    Attract,
+    /// Pushes particles away from the pointer
+    Repel,
+    /// Blends particle velocities towards the pointer's velocity
+    Drag,
+}
+
+/// Force applied to the particles within `radius` of `position`, falling off
+/// linearly towards the edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interaction {
+    pub kind: InteractionKind,
+    pub position: Vec2,
+    /// Velocity of the pointer, matched by [`InteractionKind::Drag`]
+    pub velocity: Vec2,
+    pub radius: Real,
+    /// Acceleration at the center for attraction and repulsion, or the rate at
+    /// which dragged velocities approach the pointer's velocity
+    pub strength: Real,
+}
+
+impl Interaction {
+    /// Creates an interaction at rest with a default radius and strength for `kind`.
+    #[must_use]
+    pub fn new(kind: InteractionKind, position: Vec2) -> Self {
+        Self {
+            kind,
+            position,
+            velocity: Vec2::ZERO,
+            radius: 1.0,
+            strength: match kind {
+                InteractionKind::Attract | InteractionKind::Repel => 60.0,
+                InteractionKind::Drag => 25.0,
+            },
+        }
+    }
+
+    pub(crate) fn apply(&self, p: &mut Particle, dt: Real) {
+        let dx = self.position - p.x;
+        let r = dx.length();
+        if r >= self.radius {
+            return;
+        }
+        let falloff = 1.0 - r / self.radius;
+        let direction = if r > 0.0 { dx / r } else { Vec2::ZERO };
+        match self.kind {
+            InteractionKind::Attract => p.v += self.strength * falloff * dt * direction,
+            InteractionKind::Repel => p.v -= self.strength * falloff * dt * direction,
+            InteractionKind::Drag => {
+                // exponential approach stays stable for any rate and step size
+                let blend = 1.0 - (-self.strength * falloff * dt).exp();
+                p.v += blend * (self.velocity - p.v);
+            }
+        }
+    }
+}
+
+impl State {
+    #[must_use]
+    pub fn interaction(&self) -> Option<&Interaction> {
+        self.interaction.as_ref()
+    }
+
+    /// Starts, replaces or with `None` ends the interaction applied in each solver step.
+    pub fn set_interaction(&mut self, interaction: Option<Interaction>) {
+        self.interaction = interaction;
+    }
+
+    /// Moves the current interaction to `position`, deriving its velocity from the
+    /// previous position. Meant to be called once per frame before [`State::update`].
+    pub fn move_interaction(&mut self, position: Vec2) {
+        // simulated time the next frame covers, so dragged fluid keeps up with the
+        // pointer on screen in slow motion too
+        let frame_dt = if self.paused {
+            0.0
+        } else {
+            self.params.frame_dt * self.time_scale
+        };
+        if let Some(interaction) = self.interaction.as_mut() {
+            interaction.velocity = if frame_dt > 0.0 {
+                (position - interaction.position) / frame_dt
+            } else {
+                Vec2::ZERO
+            };
+            interaction.position = position;
+        }
+    }
+}
@@ -1,83 +1,422 @@
 #![allow(
     clippy::cast_possible_truncation,
     clippy::cast_sign_loss,
-    clippy::cast_precision_loss
+    clippy::cast_precision_loss,
+    // `Real` casts are no-ops in one of the two precisions
+    clippy::unnecessary_cast
 )]
 
-use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use glam::{vec2, vec3, UVec2, Vec2, Vec3};
+use glam::UVec2;
 use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+
+mod checkpoint;
+pub mod color;
+mod diagnostics;
+mod error;
+pub mod export;
+pub mod geometry;
+pub mod interaction;
+mod query;
+mod removal;
+mod safeguard;
+pub mod scene;
+pub mod surface;
+mod thermal;
+mod transport;
+mod vorticity;
+
+pub use diagnostics::Diagnostics;
+pub use error::SolverError;
+pub use geometry::Shape;
+pub use interaction::{Interaction, InteractionKind};
+pub use query::FieldSample;
+pub use safeguard::{Recovery, Safeguard};
+pub use scene::{Emitter, Scene, SceneError};
+pub use thermal::{HeatSource, Thermal, WallTemperatures};
+pub use transport::{Injector, ScalarChannel};
 
 #[cfg(target_arch = "wasm32")]
 // must be included to init rayon thread pool with web workers
 pub use wasm_bindgen_rayon::init_thread_pool;
 
-pub const G: Vec2 = glam::vec2(0.0, -9.81);
+use precision::PI;
+pub use precision::{to_f32, to_f64, Real, Vec2, Vec3};
+
+#[cfg(not(feature = "f64"))]
+mod precision {
+    pub use glam::{Vec2, Vec3};
+    pub use std::f32::consts::PI;
+
+    /// Scalar type used throughout the solver, `f64` with the `f64` feature enabled.
+    pub type Real = f32;
+
+    /// Converts a solver vector to single precision, e.g. for uploading to the GPU.
+    #[inline]
+    #[must_use]
+    pub fn to_f32(v: Vec2) -> glam::Vec2 {
+        v
+    }
+
+    /// Converts a solver scalar to double precision, e.g. for frame counting.
+    #[inline]
+    #[must_use]
+    pub fn to_f64(v: Real) -> f64 {
+        f64::from(v)
+    }
+}
+
+#[cfg(feature = "f64")]
+mod precision {
+    pub use glam::{DVec2 as Vec2, DVec3 as Vec3};
+    pub use std::f64::consts::PI;
+
+    /// Scalar type used throughout the solver, `f64` with the `f64` feature enabled.
+    pub type Real = f64;
+
+    /// Converts a solver vector to single precision, e.g. for uploading to the GPU.
+    #[inline]
+    #[must_use]
+    pub fn to_f32(v: Vec2) -> glam::Vec2 {
+        v.as_vec2()
+    }
+
+    /// Converts a solver scalar to double precision, e.g. for frame counting.
+    #[inline]
+    #[must_use]
+    pub fn to_f64(v: Real) -> f64 {
+        v
+    }
+}
+
+pub const G: Vec2 = Vec2::new(0.0, -9.81);
 pub const WINDOW_WIDTH: u32 = 1024;
 pub const WINDOW_HEIGHT: u32 = 720;
 pub const VIEW_WIDTH: f32 = 20.0;
 pub const VIEW_HEIGHT: f32 = WINDOW_HEIGHT as f32 * VIEW_WIDTH / WINDOW_WIDTH as f32;
 
+const FRAME_DT: Real = 1.0 / 40.0;
 const SOLVER_STEPS: usize = 10;
-const REST_DENS: f32 = 45.0;
-const STIFFNESS: f32 = 0.08;
-const STIFF_APPROX: f32 = 0.1;
-const SURFACE_TENSION: f32 = 0.0001;
-const LINEAR_VISC: f32 = 0.25;
-const QUAD_VISC: f32 = 0.5;
-const PARTICLE_RADIUS: f32 = 0.03;
-const H: f32 = 6.0 * PARTICLE_RADIUS;
-const H2: f32 = H * H;
-const DT: f32 = (1.0 / 40.0) / SOLVER_STEPS as f32;
-const DT2: f32 = DT * DT;
-const KERN: f32 = 20.0 / (2.0 * PI * H * H);
-const KERN_NORM: f32 = 30.0 / (2.0 * PI * H * H);
-const EPS: f32 = 0.000_000_1;
-const EPS2: f32 = EPS * EPS;
-
-const CELL_SIZE: f32 = H; // set to smoothing radius
-const GRID_WIDTH: usize = (VIEW_WIDTH / CELL_SIZE) as usize;
-const GRID_HEIGHT: usize = (VIEW_HEIGHT / CELL_SIZE) as usize;
-const NUM_CELLS: usize = GRID_WIDTH * GRID_HEIGHT;
+const REST_DENS: Real = 45.0;
+const STIFFNESS: Real = 0.08;
+const STIFF_APPROX: Real = 0.1;
+const SURFACE_TENSION: Real = 0.0001;
+const LINEAR_VISC: Real = 0.25;
+const QUAD_VISC: Real = 0.5;
+const PARTICLE_RADIUS: Real = 0.03;
+const PARTICLE_SPACING: Real = 3.0 * PARTICLE_RADIUS;
+const H: Real = 6.0 * PARTICLE_RADIUS;
+const H2: Real = H * H;
+const KERN: Real = 20.0 / (2.0 * PI * H * H);
+const KERN_NORM: Real = 30.0 / (2.0 * PI * H * H);
+const EPS: Real = 0.000_000_1;
+const EPS2: Real = EPS * EPS;
+
+const CELL_SIZE: Real = H; // set to smoothing radius
+/// Smallest domain extent, the grid needs at least three cells in each direction
+pub const MIN_DOMAIN_SIZE: Real = 3.0 * CELL_SIZE;
+/// Largest number of grid cells of a domain, which bounds the memory of the grid
+pub const MAX_GRID_CELLS: usize = 1 << 18;
 const NUM_NEIGHBORS: usize = 64;
 pub const MAX_PARTICLES: usize = 30_000;
+/// Number of scalar channels every particle can carry, see [`State::set_scalars`]
+pub const MAX_SCALARS: usize = 4;
+
+/// Solver parameters that may change at runtime, defaults reproduce the original demo.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    pub gravity: Vec2,
+    /// Simulated time advanced by each call to [`State::update`]
+    pub frame_dt: Real,
+    /// Number of solver steps per frame
+    pub substeps: usize,
+    pub rest_density: Real,
+    pub stiffness: Real,
+    pub stiffness_near: Real,
+    pub surface_tension: Real,
+    pub linear_viscosity: Real,
+    pub quadratic_viscosity: Real,
+    /// Strength of the vorticity confinement that restores swirls lost to
+    /// numerical dissipation, in inverse seconds, zero to disable
+    pub vorticity_confinement: Real,
+    /// Fraction by which velocities are blended towards those of the neighbors
+    /// each solver step (XSPH), zero to disable
+    pub xsph: Real,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            gravity: G,
+            frame_dt: FRAME_DT,
+            substeps: SOLVER_STEPS,
+            rest_density: REST_DENS,
+            stiffness: STIFFNESS,
+            stiffness_near: STIFF_APPROX,
+            surface_tension: SURFACE_TENSION,
+            linear_viscosity: LINEAR_VISC,
+            quadratic_viscosity: QUAD_VISC,
+            vorticity_confinement: 0.0,
+            xsph: 0.0,
+        }
+    }
+}
+
+impl Parameters {
+    /// Length of a single solver step.
+    #[must_use]
+    pub fn dt(&self) -> Real {
+        self.frame_dt / self.substeps as Real
+    }
+}
+
+/// Name, range and default value of a scalar entry of [`Parameters`], e.g. for
+/// generating UI controls. Vector entries are split into `_x` and `_y` components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParameterInfo {
+    pub name: &'static str,
+    pub min: Real,
+    pub max: Real,
+    /// Granularity of sensible values, `1` for integer parameters
+    pub step: Real,
+    pub default: Real,
+}
+
+impl ParameterInfo {
+    const fn new(name: &'static str, min: Real, max: Real, step: Real, default: Real) -> Self {
+        Self {
+            name,
+            min,
+            max,
+            step,
+            default,
+        }
+    }
+}
+
+impl Parameters {
+    /// Parameters that can be tuned while the simulation runs, see [`Parameters::get`].
+    pub const INFO: [ParameterInfo; 11] = [
+        ParameterInfo::new("gravity_x", -20.0, 20.0, 0.01, G.x),
+        ParameterInfo::new("gravity_y", -20.0, 20.0, 0.01, G.y),
+        ParameterInfo::new("substeps", 1.0, 30.0, 1.0, SOLVER_STEPS as Real),
+        ParameterInfo::new("rest_density", 10.0, 100.0, 0.1, REST_DENS),
+        ParameterInfo::new("stiffness", 0.0, 0.5, 0.001, STIFFNESS),
+        ParameterInfo::new("stiffness_near", 0.0, 0.5, 0.001, STIFF_APPROX),
+        ParameterInfo::new("surface_tension", 0.0, 0.001, 0.000_01, SURFACE_TENSION),
+        ParameterInfo::new("linear_viscosity", 0.0, 2.0, 0.01, LINEAR_VISC),
+        ParameterInfo::new("quadratic_viscosity", 0.0, 2.0, 0.01, QUAD_VISC),
+        ParameterInfo::new("vorticity_confinement", 0.0, 10.0, 0.1, 0.0),
+        ParameterInfo::new("xsph", 0.0, 0.5, 0.01, 0.0),
+    ];
+
+    /// Value of the parameter called `name` in [`Parameters::INFO`].
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Real> {
+        Some(match name {
+            "gravity_x" => self.gravity.x,
+            "gravity_y" => self.gravity.y,
+            "substeps" => self.substeps as Real,
+            "rest_density" => self.rest_density,
+            "stiffness" => self.stiffness,
+            "stiffness_near" => self.stiffness_near,
+            "surface_tension" => self.surface_tension,
+            "linear_viscosity" => self.linear_viscosity,
+            "quadratic_viscosity" => self.quadratic_viscosity,
+            "vorticity_confinement" => self.vorticity_confinement,
+            "xsph" => self.xsph,
+            _ => return None,
+        })
+    }
+
+    /// Sets the parameter called `name` in [`Parameters::INFO`], clamped to its range.
+    /// Returns `false` if there is no such parameter.
+    pub fn set(&mut self, name: &str, value: Real) -> bool {
+        let Some(info) = Self::INFO.iter().find(|info| info.name == name) else {
+            return false;
+        };
+        let value = value.clamp(info.min, info.max);
+        match name {
+            "gravity_x" => self.gravity.x = value,
+            "gravity_y" => self.gravity.y = value,
+            "substeps" => self.substeps = value.round() as usize,
+            "rest_density" => self.rest_density = value,
+            "stiffness" => self.stiffness = value,
+            "stiffness_near" => self.stiffness_near = value,
+            "surface_tension" => self.surface_tension = value,
+            "linear_viscosity" => self.linear_viscosity = value,
+            "quadratic_viscosity" => self.quadratic_viscosity = value,
+            "vorticity_confinement" => self.vorticity_confinement = value,
+            "xsph" => self.xsph = value,
+            _ => unreachable!("parameter {name} is listed in INFO"),
+        }
+        true
+    }
+}
+
+/// Wall-clock time spent in each phase of the solver, see [`State::set_timings_enabled`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseTimings {
+    pub emit: Duration,
+    pub integrate_insert: Duration,
+    pub compute_forces: Duration,
+    pub project_correct: Duration,
+    /// Heat transfer, zero unless [`State::set_thermal`] enabled it
+    pub heat: Duration,
+    /// Scalar transport, zero without [`State::set_scalars`] channels
+    pub transport: Duration,
+    /// Vorticity confinement and XSPH smoothing, zero unless enabled in [`Parameters`]
+    pub vorticity: Duration,
+    /// Number of solver steps measured
+    pub steps: u64,
+}
+
+impl PhaseTimings {
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.emit
+            + self.integrate_insert
+            + self.compute_forces
+            + self.project_correct
+            + self.heat
+            + self.transport
+            + self.vorticity
+    }
+}
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Particle {
     pub x: Vec2,
     xlast: Vec2,
     v: Vec2,
-    m: f32,
-    p: f32,
-    pv: f32,
+    m: Real,
+    p: Real,
+    pv: Real,
+    d: Real,
+    /// Temperature, only changed by heat transfer
+    t: Real,
+    /// Concentrations of the scalar channels
+    c: [Real; MAX_SCALARS],
+    id: u32,
     grid_index: UVec2,
 }
 
 impl Particle {
     #[must_use]
-    pub fn new(x: f32, y: f32) -> Self {
+    pub fn new(x: Real, y: Real) -> Self {
         Self {
             x: Vec2::new(x, y),
             m: 1.0,
             ..Default::default()
         }
     }
+
+    /// Identifier that is unique within a [`State`] and stays fixed for the particle's lifetime.
+    #[must_use]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[must_use]
+    pub fn velocity(&self) -> Vec2 {
+        self.v
+    }
+
+    /// Overrides the velocity, e.g. to move particles along a prescribed path.
+    pub fn set_velocity(&mut self, v: Vec2) {
+        self.v = v;
+    }
+
+    #[must_use]
+    pub fn mass(&self) -> Real {
+        self.m
+    }
+
+    #[must_use]
+    pub fn pressure(&self) -> Real {
+        self.p
+    }
+
+    #[must_use]
+    pub fn near_pressure(&self) -> Real {
+        self.pv
+    }
+
+    #[must_use]
+    pub fn temperature(&self) -> Real {
+        self.t
+    }
+
+    pub fn set_temperature(&mut self, t: Real) {
+        self.t = t;
+    }
+
+    /// Concentration of scalar channel `channel`, zero beyond [`MAX_SCALARS`].
+    #[must_use]
+    pub fn concentration(&self, channel: usize) -> Real {
+        self.c.get(channel).copied().unwrap_or(0.0)
+    }
+
+    /// Sets the concentration of scalar channel `channel`, ignoring channels
+    /// beyond [`MAX_SCALARS`].
+    pub fn set_concentration(&mut self, channel: usize, value: Real) {
+        if let Some(c) = self.c.get_mut(channel) {
+            *c = value;
+        }
+    }
+
+    /// Density computed in the last solver step.
+    #[must_use]
+    pub fn density(&self) -> Real {
+        self.d
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct State {
     pub particles: Vec<Particle>,
     particles_initial: Vec<Particle>,
+    domain: Vec2,
+    /// Whether particles leaving through the left or right side re-enter on the other
+    periodic_x: bool,
     boundaries: [Vec3; 4],
+    obstacles: Vec<Shape>,
+    emitters: Vec<Emitter>,
     grid: Vec<Vec<usize>>,
+    grid_width: usize,
+    /// Number of particles sorted into the grid by the last solver step, later
+    /// ones were added since
+    grid_len: usize,
+    /// Largest distance a particle moved since it was sorted into the grid
+    grid_slack: Real,
     neighborhoods: Vec<Vec<Neighbor>>,
+    thread_pool: Option<Arc<ThreadPool>>,
+    params: Parameters,
+    time: Real,
+    next_id: u32,
+    timings: Option<PhaseTimings>,
+    interaction: Option<Interaction>,
+    paused: bool,
+    time_scale: Real,
+    /// Fraction of a solver step carried over between frames at time scales that
+    /// do not divide into whole steps
+    pending_steps: Real,
+    safeguard: Option<Safeguard>,
+    recovery: Option<Recovery>,
+    snapshot: safeguard::Snapshot,
+    thermal: Option<Thermal>,
+    scalars: Vec<ScalarChannel>,
 }
 
 #[derive(Debug)]
 struct Neighbor {
     index: usize,
-    r: f32,
+    r: Real,
 }
 
 impl State {
@@ -85,83 +424,374 @@ impl State {
     pub fn new() -> Self {
         let particles = Vec::with_capacity(MAX_PARTICLES);
         let particles_initial = Vec::with_capacity(MAX_PARTICLES);
-        let boundaries = [
-            vec3(1.0, 0.0, 0.0),           // left
-            vec3(0.0, 1.0, 0.0),           // bottom
-            vec3(-1.0, 0.0, -VIEW_WIDTH),  // right
-            vec3(0.0, -1.0, -VIEW_HEIGHT), // top
-        ];
-        let grid = vec![Vec::with_capacity(NUM_NEIGHBORS); NUM_CELLS];
-        Self {
+        let mut state = Self {
             particles,
             particles_initial,
-            boundaries,
-            grid,
+            time_scale: 1.0,
             ..State::default()
+        };
+        state.set_domain(Vec2::new(VIEW_WIDTH as Real, VIEW_HEIGHT as Real));
+        state
+    }
+
+    /// Size of the simulation domain, which spans from the origin to `domain()`.
+    #[must_use]
+    pub fn domain(&self) -> Vec2 {
+        self.domain
+    }
+
+    /// Resizes the simulation domain and its boundaries. Each extent is at least
+    /// [`MIN_DOMAIN_SIZE`], and the longer one is shortened until the grid has at
+    /// most [`MAX_GRID_CELLS`] cells.
+    pub fn set_domain(&mut self, size: Vec2) {
+        let max_extent = (MAX_GRID_CELLS / 3) as Real * CELL_SIZE;
+        let mut size = size
+            .max(Vec2::splat(MIN_DOMAIN_SIZE))
+            .min(Vec2::splat(max_extent));
+        let cells = (size / CELL_SIZE).floor();
+        if cells.x * cells.y > MAX_GRID_CELLS as Real {
+            let fit = |other: Real| (MAX_GRID_CELLS as Real / other).floor() * CELL_SIZE;
+            if size.x > size.y {
+                size.x = fit(cells.y);
+            } else {
+                size.y = fit(cells.x);
+            }
         }
+        self.domain = size;
+        self.boundaries = [
+            Vec3::new(1.0, 0.0, 0.0),      // left
+            Vec3::new(0.0, 1.0, 0.0),      // bottom
+            Vec3::new(-1.0, 0.0, -size.x), // right
+            Vec3::new(0.0, -1.0, -size.y), // top
+        ];
+        self.grid_width = (size.x / CELL_SIZE) as usize;
+        let grid_height = (size.y / CELL_SIZE) as usize;
+        self.grid = (0..self.grid_width * grid_height)
+            .map(|_| Vec::with_capacity(NUM_NEIGHBORS))
+            .collect();
+        self.grid_len = 0;
+    }
+
+    #[must_use]
+    pub fn periodic_x(&self) -> bool {
+        self.periodic_x
+    }
+
+    /// Makes the domain repeat along x: the left and right boundaries are removed,
+    /// particles leaving through one side re-enter on the other and interact with
+    /// neighbors across the seam. Obstacles are not repeated.
+    pub fn set_periodic_x(&mut self, periodic: bool) {
+        self.periodic_x = periodic;
+        // the columns are laid out differently in periodic domains
+        self.grid_len = 0;
+    }
+
+    /// Period along x, or zero if the domain is not periodic.
+    fn period_x(&self) -> Real {
+        if self.periodic_x {
+            self.domain.x
+        } else {
+            0.0
+        }
+    }
+
+    /// Extent of the view shown by the frontends, in simulation units. This is the
+    /// domain grown along one axis to match the window's aspect ratio.
+    #[must_use]
+    pub fn view_size(&self) -> (f32, f32) {
+        let aspect = WINDOW_WIDTH as f32 / WINDOW_HEIGHT as f32;
+        let domain = to_f32(self.domain);
+        (
+            f32::max(domain.x, domain.y * aspect),
+            f32::max(domain.y, domain.x / aspect),
+        )
+    }
+
+    #[must_use]
+    pub fn obstacles(&self) -> &[Shape] {
+        &self.obstacles
+    }
+
+    /// Adds a solid obstacle that particles are pushed out of.
+    pub fn add_obstacle(&mut self, shape: Shape) {
+        self.obstacles.push(shape);
+    }
+
+    #[must_use]
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    /// Adds an emitter that spawns particles during updates.
+    pub fn add_emitter(&mut self, emitter: Emitter) {
+        self.emitters.push(emitter);
+    }
+
+    /// Creates a state whose updates run on `thread_pool` instead of the global rayon pool.
+    /// The pool may be shared between several states, e.g. for parameter sweeps.
+    #[must_use]
+    pub fn with_thread_pool(thread_pool: Arc<ThreadPool>) -> Self {
+        Self {
+            thread_pool: Some(thread_pool),
+            ..Self::new()
+        }
+    }
+
+    /// Creates a state owning a private rayon pool with `num_threads` threads.
+    ///
+    /// # Errors
+    /// Will return `Err` if the thread pool cannot be created.
+    pub fn with_num_threads(num_threads: usize) -> Result<Self, ThreadPoolBuildError> {
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("solver-{i}"))
+            .build()?;
+        Ok(Self::with_thread_pool(Arc::new(thread_pool)))
+    }
+
+    /// Sets the pool used for updates, `None` falls back to the global rayon pool.
+    pub fn set_thread_pool(&mut self, thread_pool: Option<Arc<ThreadPool>>) {
+        self.thread_pool = thread_pool;
+    }
+
+    #[must_use]
+    pub fn thread_pool(&self) -> Option<&Arc<ThreadPool>> {
+        self.thread_pool.as_ref()
+    }
+
+    /// Runs `op` in the pool used for updates, so that parallel work outside of
+    /// updates, e.g. for exports, keeps to the same threads.
+    pub fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match &self.thread_pool {
+            Some(thread_pool) => thread_pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Number of threads used by updates.
+    #[must_use]
+    pub fn num_threads(&self) -> usize {
+        self.thread_pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            })
+    }
+
+    #[must_use]
+    pub fn params(&self) -> &Parameters {
+        &self.params
+    }
+
+    /// Replaces the parameters without validating them, so that zero `substeps`
+    /// freeze the simulation and non-finite values make it diverge. Use
+    /// [`State::try_set_params`] for parameters from user input.
+    pub fn set_params(&mut self, params: Parameters) {
+        self.params = params;
+    }
+
+    /// Enables or disables measuring the time spent in each solver phase. Enabling
+    /// resets previous measurements. Measuring uses [`Instant`], which is not
+    /// available on `wasm32-unknown-unknown`.
+    pub fn set_timings_enabled(&mut self, enabled: bool) {
+        self.timings = enabled.then(PhaseTimings::default);
+    }
+
+    /// Time spent in each phase since timings were enabled.
+    #[must_use]
+    pub fn timings(&self) -> Option<&PhaseTimings> {
+        self.timings.as_ref()
+    }
+
+    /// Simulated time since the state was created or last cleared.
+    #[must_use]
+    pub fn time(&self) -> Real {
+        self.time
+    }
+
+    #[must_use]
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes [`State::update`]. A paused state can still be advanced
+    /// with [`State::step_frame`] and [`State::step_substep`].
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Simulated time per frame relative to [`Parameters::frame_dt`].
+    #[must_use]
+    pub fn time_scale(&self) -> Real {
+        self.time_scale
+    }
+
+    /// Sets how much simulated time passes per call to [`State::update`], e.g. `0.25`
+    /// for slow motion or `2` to fast-forward. The step size is unaffected, instead
+    /// each frame runs proportionally more or fewer solver steps.
+    pub fn set_time_scale(&mut self, time_scale: Real) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    /// Number of neighbors each particle interacted with in the last solver step,
+    /// at most `NUM_NEIGHBORS`.
+    #[must_use]
+    pub fn neighbor_counts(&self) -> Vec<usize> {
+        let mut counts: Vec<usize> = self.neighborhoods.iter().map(Vec::len).collect();
+        // particles emitted or loaded since the last step have no neighborhood yet
+        counts.resize(self.particles.len(), 0);
+        counts
     }
 
     pub fn clear(&mut self) {
         self.particles.clear();
         self.particles_initial.clear();
         self.neighborhoods.clear();
+        self.grid_len = 0;
+        self.time = 0.0;
+        self.pending_steps = 0.0;
+        self.next_id = 0;
+        self.emitters.iter_mut().for_each(Emitter::reset);
     }
 
     fn place_particle(&mut self, start: Vec2) {
-        self.particles.push(Particle::new(start.x, start.y));
+        self.place_particle_with_velocity(start, Vec2::ZERO);
+    }
+
+    fn place_particle_with_velocity(&mut self, start: Vec2, v: Vec2) {
+        self.particles.push(Particle {
+            id: self.next_id,
+            v,
+            t: self
+                .thermal
+                .as_ref()
+                .map_or(0.0, |t| t.reference_temperature),
+            ..Particle::new(start.x, start.y)
+        });
+        self.next_id += 1;
         self.particles_initial.push(Particle::default());
         self.neighborhoods.push(Vec::with_capacity(NUM_NEIGHBORS));
     }
 
     fn place_square(&mut self, start: &mut Vec2, num_particles: usize) -> usize {
         let x0 = start.x;
-        let num = f32::sqrt(num_particles as f32) as usize;
+        let num = Real::sqrt(num_particles as Real) as usize;
         for _ in 0..num {
             for _ in 0..num {
                 self.place_particle(*start);
-                start.x += 2.0 * PARTICLE_RADIUS + PARTICLE_RADIUS;
+                start.x += PARTICLE_SPACING;
             }
             start.x = x0;
-            start.y -= 2.0 * PARTICLE_RADIUS + PARTICLE_RADIUS;
+            start.y -= PARTICLE_SPACING;
         }
         num * num
     }
 
+    /// Fills `shape` with particles on the initial lattice, all moving with
+    /// `velocity`. Returns the number of particles added, which is limited by
+    /// [`MAX_PARTICLES`].
+    pub fn fill_shape(&mut self, shape: &Shape, velocity: Vec2) -> usize {
+        self.fill_points(&shape.lattice(PARTICLE_SPACING), velocity)
+    }
+
+    /// Like [`State::fill_shape`] for a lattice that was already built.
+    pub(crate) fn fill_points(&mut self, points: &[Vec2], velocity: Vec2) -> usize {
+        let num = points
+            .len()
+            .min(MAX_PARTICLES.saturating_sub(self.particles.len()));
+        for x in &points[..num] {
+            self.place_particle_with_velocity(*x, velocity);
+        }
+        num
+    }
+
     pub fn init_dam_break(&mut self, num_particles: usize) {
-        let mut start = vec2(0.25 * VIEW_WIDTH, 0.95 * VIEW_HEIGHT);
+        let mut start = Vec2::new(0.25 * self.domain.x, 0.95 * self.domain.y);
         self.place_square(&mut start, num_particles);
     }
 
     pub fn init_block(&mut self, num_particles: usize) {
-        let mut start = vec2(
-            VIEW_WIDTH / 2.0 - VIEW_HEIGHT / 10.0,
-            VIEW_HEIGHT - VIEW_HEIGHT / 10.0,
+        let mut start = Vec2::new(
+            self.domain.x / 2.0 - self.domain.y / 10.0,
+            self.domain.y - self.domain.y / 10.0,
         );
         self.place_square(&mut start, num_particles);
     }
 
+    fn emit(&mut self) {
+        if self.emitters.is_empty() {
+            return;
+        }
+        let dt = self.params.dt();
+        let time = self.time;
+        let mut spawned = Vec::new();
+        let mut sources = Vec::new();
+        for (i, emitter) in self.emitters.iter_mut().enumerate() {
+            emitter.emit(time, dt, &mut spawned);
+            sources.resize(spawned.len(), i);
+        }
+        let free = MAX_PARTICLES.saturating_sub(self.particles.len());
+        for (x, i) in spawned.into_iter().zip(sources).take(free) {
+            let emitter = &self.emitters[i];
+            let (v, temperature) = (emitter.velocity, emitter.temperature);
+            let c = transport::concentrations(&emitter.concentrations);
+            self.place_particle_with_velocity(x, v);
+            if let Some(p) = self.particles.last_mut() {
+                p.t = temperature.unwrap_or(p.t);
+                p.c = c;
+            }
+        }
+    }
+
     #[allow(clippy::similar_names)]
     fn integrate_insert(&mut self) {
+        let dt = self.params.dt();
+        let gravity = self.params.gravity;
+        let (expansion, reference_temperature) =
+            (self.thermal.as_ref()).map_or((0.0, 0.0), |t| (t.expansion, t.reference_temperature));
+        let interaction = self.interaction;
+        let grid_width = self.grid_width;
+        let grid_height = self.grid.len() / grid_width;
+        let period = self.period_x();
+        // periodic domains use every column, stretched to tile the period exactly
+        let (cell_width, min_column, max_column) = if period > 0.0 {
+            (period / grid_width as Real, 0, grid_width - 1)
+        } else {
+            (CELL_SIZE, 1, grid_width - 2)
+        };
+        self.grid_len = self.particles.len();
         let grid = &mut self.grid;
         grid.iter_mut().for_each(std::vec::Vec::clear);
         self.particles.iter_mut().enumerate().for_each(|(i, p)| {
-            p.v += G * DT;
+            // Boussinesq approximation, warm fluid weighs less
+            p.v += gravity * (1.0 - expansion * (p.t - reference_temperature)) * dt;
+            if let Some(interaction) = &interaction {
+                interaction.apply(p, dt);
+            }
             p.xlast = p.x;
-            p.x += DT * p.v;
+            p.x += dt * p.v;
+            if period > 0.0 {
+                let shift = period * (p.x.x / period).floor();
+                p.x.x -= shift;
+                p.xlast.x -= shift;
+            }
 
-            let xind = (p.x.x / CELL_SIZE).floor() as usize;
+            let xind = (p.x.x / cell_width).floor() as usize;
             let yind = (p.x.y / CELL_SIZE).floor() as usize;
-            let xind = usize::max(1, usize::min(GRID_WIDTH - 2, xind));
-            let yind = usize::max(1, usize::min(GRID_HEIGHT - 2, yind));
-            grid[xind + yind * GRID_WIDTH].push(i);
-            p.grid_index = UVec2::new(xind as u32, (yind * GRID_WIDTH) as u32);
+            let xind = usize::max(min_column, usize::min(max_column, xind));
+            let yind = usize::max(1, usize::min(grid_height - 2, yind));
+            grid[xind + yind * grid_width].push(i);
+            p.grid_index = UVec2::new(xind as u32, (yind * grid_width) as u32);
         });
     }
 
     fn compute_forces(&mut self) {
         // TODO can we get around this copy
         self.particles_initial.copy_from_slice(&self.particles);
+        let params = &self.params;
         let grid = &self.grid;
+        let grid_width = self.grid_width as u32;
+        let period = self.period_x();
         self.particles
             .par_iter_mut()
             .zip_eq(self.neighborhoods.par_iter_mut())
@@ -169,36 +799,46 @@ impl State {
                 ni.clear();
                 let mut dens = 0.0;
                 let mut dens_proj = 0.0;
-                for gx in (pi.grid_index.x - 1)..=(pi.grid_index.x + 1) {
-                    let y_range = (pi.grid_index.y - GRID_WIDTH as u32)
-                        ..=(pi.grid_index.y + GRID_WIDTH as u32);
-                    for gy in y_range.step_by(GRID_WIDTH) {
-                        for j in &grid[(gx + gy) as usize] {
-                            let pj = self.particles_initial[*j];
-                            let dx = pj.x - pi.x;
-                            let r2 = dx.length_squared();
-                            if !(EPS2..=H2).contains(&r2) {
-                                continue;
-                            }
-                            let r = f32::sqrt(r2);
-                            let a = 1.0 - r / H;
-                            dens += pj.m * a * a * a * KERN;
-                            dens_proj += pj.m * a * a * a * a * KERN_NORM;
-                            if ni.len() < NUM_NEIGHBORS {
-                                ni.push(Neighbor { index: *j, r });
-                            }
+                for cell in neighbor_cells(pi.grid_index, grid_width, period > 0.0) {
+                    for j in &grid[cell] {
+                        let pj = self.particles_initial[*j];
+                        let dx = minimum_image(pj.x - pi.x, period);
+                        let r2 = dx.length_squared();
+                        if !(EPS2..=H2).contains(&r2) {
+                            continue;
+                        }
+                        let r = Real::sqrt(r2);
+                        let a = 1.0 - r / H;
+                        dens += pj.m * a * a * a * KERN;
+                        dens_proj += pj.m * a * a * a * a * KERN_NORM;
+                        if ni.len() < NUM_NEIGHBORS {
+                            ni.push(Neighbor { index: *j, r });
                         }
                     }
                 }
-                pi.p = STIFFNESS * (dens - pi.m * REST_DENS);
-                pi.pv = STIFF_APPROX * dens_proj;
+                pi.d = dens;
+                pi.p = params.stiffness * (dens - pi.m * params.rest_density);
+                pi.pv = params.stiffness_near * dens_proj;
             });
     }
 
     fn project_correct(&mut self) {
         // TODO can we get around this copy?
         self.particles_initial.copy_from_slice(&self.particles);
-        let bounds = self.boundaries;
+        let params = &self.params;
+        let dt = params.dt();
+        let dt2 = dt * dt;
+        let period = self.period_x();
+        // periodic domains only keep the bottom and top boundaries, whose normals have no x part
+        let bounds: Vec<Vec3> = (self.boundaries.iter())
+            .filter(|b| period == 0.0 || b.x == 0.0)
+            .copied()
+            .collect();
+        let obstacles = &self.obstacles;
+        let (viscosity_falloff, reference_temperature) = (self.thermal.as_ref())
+            .map_or((0.0, 0.0), |t| {
+                (t.viscosity_falloff, t.reference_temperature)
+            });
         self.particles
             .par_iter_mut()
             .zip_eq(self.neighborhoods.par_iter_mut())
@@ -208,9 +848,9 @@ impl State {
                 for neighbor in ni {
                     let pj = self.particles_initial[neighbor.index];
                     let r = neighbor.r;
-                    let dx = pj.x - pi.x;
+                    let dx = minimum_image(pj.x - pi.x, period);
                     let a = 1.0 - r / H;
-                    let d = DT2
+                    let d = dt2
                         * ((pi.pv + pj.pv) * a * a * a * KERN_NORM + (pi.p + pj.p) * a * a * KERN)
                         / 2.0;
 
@@ -218,37 +858,147 @@ impl State {
                     xproj -= d * dx / (r * pi.m);
 
                     // surface tension
-                    xproj += (SURFACE_TENSION / pi.m) * pj.m * a * a * KERN * dx;
+                    xproj += (params.surface_tension / pi.m) * pj.m * a * a * KERN * dx;
 
                     // linear and quadratic visc
                     let dv = pi.v - pj.v;
                     let mut u = dv.dot(dx);
                     if u > 0.0 {
                         u /= r;
-                        let big_i = 0.5 * DT * a * (LINEAR_VISC * u + QUAD_VISC * u * u);
-                        xproj -= big_i * dx * DT;
+                        let viscosity_scale = if viscosity_falloff == 0.0 {
+                            1.0
+                        } else {
+                            let t = 0.5 * (pi.t + pj.t) - reference_temperature;
+                            (-viscosity_falloff * t).exp()
+                        };
+                        let big_i = 0.5
+                            * dt
+                            * a
+                            * viscosity_scale
+                            * (params.linear_viscosity * u + params.quadratic_viscosity * u * u);
+                        xproj -= big_i * dx * dt;
                     }
                 }
 
                 // correct
                 pi.x = xproj;
-                pi.v = (xproj - pi.xlast) / DT;
+                pi.v = (xproj - pi.xlast) / dt;
 
                 // boundary
                 for b in &bounds {
-                    let d = f32::max(pi.x.x * b.x + pi.x.y * b.y - b.z, 0.0);
+                    let d = Real::max(pi.x.x * b.x + pi.x.y * b.y - b.z, 0.0);
+                    if d < PARTICLE_RADIUS {
+                        pi.v += (PARTICLE_RADIUS - d) * Vec2::new(b.x, b.y) / dt;
+                    }
+                }
+                for obstacle in obstacles {
+                    let (d, normal) = obstacle.signed_distance(pi.x);
                     if d < PARTICLE_RADIUS {
-                        pi.v += (PARTICLE_RADIUS - d) * Vec2::new(b.x, b.y) / DT;
+                        pi.v += (PARTICLE_RADIUS - d) * normal / dt;
                     }
                 }
             });
     }
 
+    /// Advances the simulation by one frame scaled by [`State::time_scale`], unless paused.
+    /// Blow-ups are rolled back and retried if a [`Safeguard`] is set.
     pub fn update(&mut self) {
-        for _ in 0..SOLVER_STEPS {
-            self.integrate_insert();
-            self.compute_forces();
-            self.project_correct();
+        self.recovery = None;
+        if self.paused {
+            return;
+        }
+        match self.safeguard {
+            Some(safeguard) => self.update_guarded(&safeguard),
+            None => self.advance_frame(),
         }
     }
+
+    fn advance_frame(&mut self) {
+        self.pending_steps += self.time_scale * self.params.substeps as Real;
+        let steps = self.pending_steps.floor();
+        self.pending_steps -= steps;
+        self.advance(steps as usize);
+    }
+
+    /// Advances the simulation by exactly one frame, even while paused.
+    pub fn step_frame(&mut self) {
+        self.advance(self.params.substeps);
+    }
+
+    /// Advances the simulation by a single solver step, even while paused.
+    pub fn step_substep(&mut self) {
+        self.advance(1);
+    }
+
+    fn advance(&mut self, steps: usize) {
+        match self.thread_pool.clone() {
+            Some(thread_pool) => thread_pool.install(|| self.step(steps)),
+            None => self.step(steps),
+        }
+    }
+
+    fn step(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.run_phase(Self::emit, |t| &mut t.emit);
+            self.run_phase(Self::integrate_insert, |t| &mut t.integrate_insert);
+            self.run_phase(Self::compute_forces, |t| &mut t.compute_forces);
+            self.run_phase(Self::project_correct, |t| &mut t.project_correct);
+            if self.params.vorticity_confinement > 0.0 || self.params.xsph > 0.0 {
+                self.run_phase(Self::refine_velocities, |t| &mut t.vorticity);
+            }
+            if self.thermal.is_some() {
+                self.run_phase(Self::transfer_heat, |t| &mut t.heat);
+            }
+            if !self.scalars.is_empty() {
+                self.run_phase(Self::transport_scalars, |t| &mut t.transport);
+            }
+            self.time += self.params.dt();
+            if let Some(timings) = self.timings.as_mut() {
+                timings.steps += 1;
+            }
+        }
+        if steps > 0 {
+            self.update_grid_slack();
+        }
+    }
+
+    fn run_phase(&mut self, phase: fn(&mut Self), slot: fn(&mut PhaseTimings) -> &mut Duration) {
+        if self.timings.is_none() {
+            phase(self);
+            return;
+        }
+        let start = Instant::now();
+        phase(self);
+        if let Some(timings) = self.timings.as_mut() {
+            *slot(timings) += start.elapsed();
+        }
+    }
+}
+
+/// Indices of the 3x3 grid cells around the cell at `index`, whose `y` is already
+/// multiplied by the grid width. Columns wrap around in periodic domains.
+fn neighbor_cells(index: UVec2, grid_width: u32, periodic: bool) -> impl Iterator<Item = usize> {
+    let columns = if periodic {
+        [
+            (index.x + grid_width - 1) % grid_width,
+            index.x,
+            (index.x + 1) % grid_width,
+        ]
+    } else {
+        [index.x - 1, index.x, index.x + 1]
+    };
+    let rows = [index.y - grid_width, index.y, index.y + grid_width];
+    columns
+        .into_iter()
+        .flat_map(move |x| rows.map(|y| (x + y) as usize))
+}
+
+/// Shortest displacement equivalent to `dx` in a domain repeating every `period`
+/// along x, or `dx` itself for a period of zero.
+#[inline]
+fn minimum_image(mut dx: Vec2, period: Real) -> Vec2 {
+    if period > 0.0 {
+        dx.x -= period * (dx.x / period).round();
+    }
+    dx
 }
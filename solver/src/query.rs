@@ -0,0 +1,166 @@
+//! Spatial queries backed by the grid of the last solver step.
+
+use rayon::prelude::*;
+
+use crate::{minimum_image, Real, State, Vec2, CELL_SIZE, EPS2, H, H2, KERN};
+
+/// Fields interpolated from the particles around a point, see [`State::sample`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FieldSample {
+    /// Kernel-weighted mass density, comparable to [`crate::Particle::density`]
+    pub density: Real,
+    /// Velocity averaged over the neighbors, weighted by their kernel volume
+    pub velocity: Vec2,
+    /// Pressure averaged like `velocity`
+    pub pressure: Real,
+    /// Particles within the smoothing radius. Velocity and pressure are zero
+    /// without any.
+    pub neighbors: usize,
+}
+
+impl State {
+    /// Indices of the particles within `radius` of `center`.
+    #[must_use]
+    pub fn particles_within(&self, center: Vec2, radius: Real) -> Vec<usize> {
+        let period = self.period_x();
+        let r2 = radius * radius;
+        let mut found = Vec::new();
+        self.for_each_candidate(center - radius, center + radius, |i| {
+            if minimum_image(self.particles[i].x - center, period).length_squared() <= r2 {
+                found.push(i);
+            }
+        });
+        found
+    }
+
+    /// Indices of the particles inside the rectangle from `min` to `max`.
+    #[must_use]
+    pub fn particles_in_rect(&self, min: Vec2, max: Vec2) -> Vec<usize> {
+        let period = self.period_x();
+        let mut found = Vec::new();
+        self.for_each_candidate(min, max, |i| {
+            let mut x = self.particles[i].x;
+            if period > 0.0 {
+                x.x = min.x + (x.x - min.x).rem_euclid(period);
+            }
+            if x.cmpge(min).all() && x.cmple(max).all() {
+                found.push(i);
+            }
+        });
+        found
+    }
+
+    /// Indices of the `k` particles closest to `point`, nearest first.
+    #[must_use]
+    pub fn k_nearest(&self, point: Vec2, k: usize) -> Vec<usize> {
+        let period = self.period_x();
+        let distance =
+            |i: usize| minimum_image(self.particles[i].x - point, period).length_squared();
+        // widen the search until it holds k particles, which are then the nearest
+        let mut radius = H;
+        let mut found = loop {
+            if radius > self.domain.length() {
+                break (0..self.particles.len()).collect();
+            }
+            let found = self.particles_within(point, radius);
+            if found.len() >= k {
+                break found;
+            }
+            radius *= 2.0;
+        };
+        found.sort_by(|&a, &b| distance(a).total_cmp(&distance(b)));
+        found.truncate(k);
+        found
+    }
+
+    /// Interpolates the fluid fields at `point` with the density kernel of the
+    /// solver, from the densities and pressures of the last solver step. Like in
+    /// the solver, a particle exactly at `point` is left out, so that sampling at a
+    /// particle reproduces its density.
+    #[must_use]
+    pub fn sample(&self, point: Vec2) -> FieldSample {
+        let period = self.period_x();
+        let mut sample = FieldSample::default();
+        let mut weight_sum = 0.0;
+        self.for_each_candidate(point - H, point + H, |i| {
+            let p = &self.particles[i];
+            let r2 = minimum_image(p.x - point, period).length_squared();
+            if !(EPS2..=H2).contains(&r2) {
+                return;
+            }
+            let a = 1.0 - Real::sqrt(r2) / H;
+            let w = a * a * a * KERN;
+            sample.density += p.m * w;
+            sample.neighbors += 1;
+            // particles emitted since the last step have no density yet
+            if p.d > 0.0 {
+                let weight = p.m / p.d * w;
+                sample.velocity += weight * p.v;
+                sample.pressure += weight * p.p;
+                weight_sum += weight;
+            }
+        });
+        if weight_sum > 0.0 {
+            sample.velocity /= weight_sum;
+            sample.pressure /= weight_sum;
+        }
+        sample
+    }
+
+    /// Calls `f` with every particle that may lie inside the box from `min` to
+    /// `max`, and some outside it. The grid cells are widened by the distance the
+    /// particles moved since they were sorted into them, and particles added
+    /// since are visited one by one.
+    fn for_each_candidate(&self, min: Vec2, max: Vec2, mut f: impl FnMut(usize)) {
+        let n = self.particles.len();
+        let grid_len = self.grid_len.min(n);
+        if grid_len > 0 {
+            let (min, max) = (min - self.grid_slack, max + self.grid_slack);
+            let grid_width = self.grid_width;
+            let grid_height = self.grid.len() / grid_width;
+            let rows = row(min.y, grid_height)..=row(max.y, grid_height);
+            let period = self.period_x();
+            let columns: Vec<usize> = if period > 0.0 {
+                let cell_width = period / grid_width as Real;
+                let first = (min.x / cell_width).floor() as i64;
+                let last = (max.x / cell_width).floor() as i64;
+                let span = usize::try_from(last - first + 1).unwrap_or(0);
+                (0..span.min(grid_width))
+                    .map(|c| (first + c as i64).rem_euclid(grid_width as i64) as usize)
+                    .collect()
+            } else {
+                // same clamping as when sorting particles into the grid
+                let column =
+                    |x: Real| ((x / CELL_SIZE).floor().max(0.0) as usize).clamp(1, grid_width - 2);
+                (column(min.x)..=column(max.x)).collect()
+            };
+            for y in rows {
+                for &x in &columns {
+                    for &i in &self.grid[x + y * grid_width] {
+                        if i < grid_len {
+                            f(i);
+                        }
+                    }
+                }
+            }
+        }
+        (grid_len..n).for_each(f);
+    }
+
+    /// Measures how far the particles moved since the last solver step sorted
+    /// them into the grid, which `particles_initial` still holds.
+    pub(crate) fn update_grid_slack(&mut self) {
+        let period = self.period_x();
+        self.grid_slack = self
+            .particles
+            .par_iter()
+            .zip(&self.particles_initial)
+            .map(|(p, initial)| minimum_image(p.x - initial.x, period).length())
+            .reduce(|| 0.0, Real::max);
+    }
+}
+
+/// Grid row of height `y`, clamped like when sorting particles into the grid.
+fn row(y: Real, grid_height: usize) -> usize {
+    ((y / CELL_SIZE).floor().max(0.0) as usize).clamp(1, grid_height - 2)
+}
@@ -0,0 +1,81 @@
+//! Removal of particles, e.g. for erasers, drains and trimming scenes.
+
+use crate::{Particle, Shape, State};
+
+impl State {
+    /// Removes the particles at `indices`, ignoring indices out of range and
+    /// duplicates. The remaining particles keep their order and ids. Returns the
+    /// number of particles removed.
+    pub fn remove_particles(&mut self, indices: impl IntoIterator<Item = usize>) -> usize {
+        let mut keep = vec![true; self.particles.len()];
+        for i in indices {
+            if let Some(k) = keep.get_mut(i) {
+                *k = false;
+            }
+        }
+        self.retain_mask(&keep)
+    }
+
+    /// Removes the particles for which `predicate` returns `true`. Returns the
+    /// number of particles removed.
+    pub fn remove_particles_where(
+        &mut self,
+        mut predicate: impl FnMut(&Particle) -> bool,
+    ) -> usize {
+        let keep: Vec<bool> = self.particles.iter().map(|p| !predicate(p)).collect();
+        self.retain_mask(&keep)
+    }
+
+    /// Removes the particles inside `shape`. Returns the number of particles removed.
+    pub fn remove_particles_in(&mut self, shape: &Shape) -> usize {
+        self.remove_particles_where(|p| shape.contains(p.x))
+    }
+
+    /// Keeps the particles whose entry in `keep` is `true` and renumbers the
+    /// neighborhoods and grid of the last solver step to match, so that they stay
+    /// valid until the next step rebuilds them.
+    fn retain_mask(&mut self, keep: &[bool]) -> usize {
+        let mut new_index = Vec::with_capacity(keep.len());
+        let mut kept = 0;
+        for &k in keep {
+            new_index.push(k.then_some(kept));
+            kept += usize::from(k);
+        }
+        let removed = keep.len() - kept;
+        if removed == 0 {
+            return 0;
+        }
+        let grid_len = self.grid_len.min(keep.len());
+        self.grid_len = keep[..grid_len].iter().filter(|&&k| k).count();
+
+        let mut flags = keep.iter();
+        self.particles.retain(|_| *flags.next().unwrap_or(&true));
+        let mut flags = keep.iter();
+        self.particles_initial
+            .retain(|_| *flags.next().unwrap_or(&true));
+        let mut flags = keep.iter();
+        self.neighborhoods
+            .retain(|_| *flags.next().unwrap_or(&true));
+
+        let remap = |i: usize| new_index.get(i).copied().flatten();
+        for neighbors in &mut self.neighborhoods {
+            neighbors.retain_mut(|n| match remap(n.index) {
+                Some(i) => {
+                    n.index = i;
+                    true
+                }
+                None => false,
+            });
+        }
+        for cell in &mut self.grid {
+            cell.retain_mut(|i| match remap(*i) {
+                Some(j) => {
+                    *i = j;
+                    true
+                }
+                None => false,
+            });
+        }
+        removed
+    }
+}
@@ -0,0 +1,180 @@
+//! Detection of and recovery from solver blow-ups, see [`State::set_safeguard`].
+
+use std::fmt;
+
+use crate::{Emitter, Particle, Real, SolverError, State, H, NUM_NEIGHBORS};
+
+/// Limits beyond which a frame is considered to have blown up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Safeguard {
+    /// Largest distance a particle may travel in a single solver step, in
+    /// smoothing radii. Faster particles skip past their neighbors, which is how
+    /// most blow-ups start.
+    pub max_step_distance: Real,
+    /// Largest relative compression `ρ / ρ₀ - 1` of any particle
+    pub max_density_error: Real,
+    /// Substep count beyond which retrying is given up
+    pub max_substeps: usize,
+}
+
+impl Default for Safeguard {
+    fn default() -> Self {
+        Self {
+            max_step_distance: 2.0,
+            max_density_error: 10.0,
+            max_substeps: 80,
+        }
+    }
+}
+
+/// What the [`Safeguard`] did during the last [`State::update`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    /// Simulated time after the update
+    pub time: Real,
+    /// Problem detected in the first attempt of the frame
+    pub cause: SolverError,
+    /// Substep count of the first attempt
+    pub initial_substeps: usize,
+    /// Substep count in effect after the update
+    pub substeps: usize,
+    /// Number of rollbacks
+    pub retries: usize,
+    /// Whether a retry succeeded. Otherwise the frame was rolled back with the
+    /// original substep count and the state paused.
+    pub recovered: bool,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at t = {:.3}s, ", self.cause, self.time)?;
+        if self.recovered {
+            write!(
+                f,
+                "recovered by raising the substeps from {} to {}",
+                self.initial_substeps, self.substeps
+            )
+        } else {
+            write!(
+                f,
+                "rolled back and paused after {} failed retries",
+                self.retries
+            )
+        }
+    }
+}
+
+/// Copy of the state at the start of a frame.
+#[derive(Debug, Default)]
+pub(crate) struct Snapshot {
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    time: Real,
+    next_id: u32,
+    pending_steps: Real,
+}
+
+impl State {
+    #[must_use]
+    pub fn safeguard(&self) -> Option<&Safeguard> {
+        self.safeguard.as_ref()
+    }
+
+    /// Enables or disables the safeguard of [`State::update`]. While enabled, every
+    /// frame is checked for non-finite values, particles escaping the domain,
+    /// velocity spikes and density blow-ups. A failed frame is rolled back and
+    /// retried with twice the substeps, i.e. half the timestep, until it succeeds
+    /// or [`Safeguard::max_substeps`] is reached, see [`State::last_recovery`].
+    /// A raised substep count is kept for the following frames.
+    pub fn set_safeguard(&mut self, safeguard: Option<Safeguard>) {
+        self.safeguard = safeguard;
+    }
+
+    /// Report of the safeguard if it intervened during the last [`State::update`].
+    #[must_use]
+    pub fn last_recovery(&self) -> Option<&Recovery> {
+        self.recovery.as_ref()
+    }
+
+    pub(crate) fn update_guarded(&mut self, safeguard: &Safeguard) {
+        self.save_snapshot();
+        self.advance_frame();
+        let Err(cause) = self.inspect(safeguard) else {
+            return;
+        };
+
+        let initial_substeps = self.params.substeps;
+        let mut retries = 0;
+        let recovered = loop {
+            self.restore_snapshot();
+            let substeps = 2 * self.params.substeps;
+            if substeps > safeguard.max_substeps {
+                break false;
+            }
+            self.params.substeps = substeps;
+            retries += 1;
+            self.advance_frame();
+            if self.inspect(safeguard).is_ok() {
+                break true;
+            }
+        };
+        if !recovered {
+            self.params.substeps = initial_substeps;
+            self.paused = true;
+        }
+        self.recovery = Some(Recovery {
+            time: self.time,
+            cause,
+            initial_substeps,
+            substeps: self.params.substeps,
+            retries,
+            recovered,
+        });
+    }
+
+    /// Like [`State::check`], additionally applying the limits of `safeguard`.
+    fn inspect(&self, safeguard: &Safeguard) -> Result<(), SolverError> {
+        self.check()?;
+        let max_speed = safeguard.max_step_distance * H / self.params.dt();
+        let rest_density = self.params.rest_density;
+        for (index, p) in self.particles.iter().enumerate() {
+            let speed = p.v.length();
+            if speed > max_speed {
+                return Err(SolverError::VelocitySpike { index, speed });
+            }
+            let density_error = p.d / (p.m * rest_density) - 1.0;
+            if density_error > safeguard.max_density_error {
+                return Err(SolverError::DensityBlowUp {
+                    index,
+                    density_error,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn save_snapshot(&mut self) {
+        let snapshot = &mut self.snapshot;
+        snapshot.particles.clone_from(&self.particles);
+        snapshot.emitters.clone_from(&self.emitters);
+        snapshot.time = self.time;
+        snapshot.next_id = self.next_id;
+        snapshot.pending_steps = self.pending_steps;
+    }
+
+    fn restore_snapshot(&mut self) {
+        let snapshot = &self.snapshot;
+        self.particles.clone_from(&snapshot.particles);
+        self.emitters.clone_from(&snapshot.emitters);
+        self.time = snapshot.time;
+        self.next_id = snapshot.next_id;
+        self.pending_steps = snapshot.pending_steps;
+        // the grid holds the positions of the failed attempt
+        self.grid_len = 0;
+        // drop the bookkeeping of particles emitted by the failed attempt
+        let n = self.particles.len();
+        self.particles_initial.resize(n, Particle::default());
+        self.neighborhoods
+            .resize_with(n, || Vec::with_capacity(NUM_NEIGHBORS));
+    }
+}
@@ -0,0 +1,423 @@
+//! Declarative scene descriptions loaded from TOML.
+//!
+//! ```toml
+//! duration = 10.0
+//!
+//! [domain]
+//! width = 20.0
+//! height = 14.0625
+//! periodic_x = false
+//!
+//! [parameters]
+//! gravity = [0.0, -9.81]
+//! substeps = 10
+//!
+//! [[fluid]]
+//! shape = { type = "rectangle", min = [5.0, 6.7], max = [11.7, 13.4] }
+//!
+//! [[obstacle]]
+//! shape = { type = "circle", center = [14.0, 3.0], radius = 1.0 }
+//!
+//! [thermal]
+//! reference_temperature = 20.0
+//! expansion = 0.01
+//! walls = { bottom = 60.0 }
+//!
+//! [[thermal.source]]
+//! shape = { type = "circle", center = [14.0, 3.0], radius = 1.0 }
+//! temperature = 0.0
+//! rate = 10.0
+//!
+//! [[emitter]]
+//! shape = { type = "polygon", vertices = [[1.0, 10.0], [2.0, 10.0], [1.5, 11.0]] }
+//! velocity = [3.0, 0.0]
+//! rate = 200.0
+//! stop = 4.0
+//! concentrations = [1.0]
+//!
+//! [[scalar]]
+//! name = "dye"
+//! diffusivity = 0.001
+//! color = [0.9, 0.2, 0.2]
+//!
+//! [[scalar.injector]]
+//! shape = { type = "circle", center = [3.0, 12.0], radius = 0.5 }
+//! concentration = 1.0
+//! rate = 20.0
+//! ```
+//!
+//! Every section is optional, missing parameters keep their defaults and the
+//! domain defaults to the original view.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    transport, Parameters, Real, ScalarChannel, Shape, State, Thermal, Vec2, CELL_SIZE,
+    MAX_GRID_CELLS, MAX_PARTICLES, MIN_DOMAIN_SIZE, VIEW_HEIGHT, VIEW_WIDTH,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    /// Simulated time after which headless runs stop
+    pub duration: Option<Real>,
+    pub domain: Domain,
+    pub parameters: Parameters,
+    #[serde(rename = "fluid")]
+    pub fluids: Vec<Fluid>,
+    #[serde(rename = "obstacle")]
+    pub obstacles: Vec<Obstacle>,
+    #[serde(rename = "emitter")]
+    pub emitters: Vec<Emitter>,
+    /// Enables heat transfer
+    pub thermal: Option<Thermal>,
+    /// Passive scalar channels, at most [`crate::MAX_SCALARS`]
+    #[serde(rename = "scalar")]
+    pub scalars: Vec<ScalarChannel>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Domain {
+    pub width: Real,
+    pub height: Real,
+    /// Connects the left and right sides, see [`State::set_periodic_x`]
+    #[serde(default)]
+    pub periodic_x: bool,
+}
+
+impl Default for Domain {
+    fn default() -> Self {
+        Self {
+            width: VIEW_WIDTH as Real,
+            height: VIEW_HEIGHT as Real,
+            periodic_x: false,
+        }
+    }
+}
+
+/// Region filled with particles when the scene is loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fluid {
+    #[serde(default)]
+    pub velocity: Vec2,
+    /// Initial temperature, defaults to the reference temperature of `thermal`
+    #[serde(default)]
+    pub temperature: Option<Real>,
+    /// Initial concentrations of the first scalar channels, the others start at zero
+    #[serde(default)]
+    pub concentrations: Vec<Real>,
+    pub shape: Shape,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Obstacle {
+    pub shape: Shape,
+}
+
+/// Spawns particles inside `shape` at `rate` particles per second while the
+/// simulated time is within `[start, stop)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Emitter {
+    #[serde(default)]
+    pub velocity: Vec2,
+    pub rate: Real,
+    #[serde(default)]
+    pub start: Real,
+    #[serde(default)]
+    pub stop: Option<Real>,
+    /// Temperature of spawned particles, defaults to the reference temperature
+    #[serde(default)]
+    pub temperature: Option<Real>,
+    /// Concentrations of the first scalar channels in spawned particles, the
+    /// others are zero
+    #[serde(default)]
+    pub concentrations: Vec<Real>,
+    pub shape: Shape,
+    /// Fractional particles carried over between steps
+    #[serde(skip)]
+    pub(crate) pending: Real,
+    #[serde(skip)]
+    pub(crate) emitted: u64,
+}
+
+impl Emitter {
+    #[must_use]
+    pub fn new(shape: Shape, velocity: Vec2, rate: Real) -> Self {
+        Self {
+            shape,
+            velocity,
+            rate,
+            start: 0.0,
+            stop: None,
+            temperature: None,
+            concentrations: Vec::new(),
+            pending: 0.0,
+            emitted: 0,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.pending = 0.0;
+        self.emitted = 0;
+    }
+
+    /// Appends the positions of the particles spawned during the step starting at `time`.
+    /// Positions follow a Halton sequence over the shape's bounds, so emission
+    /// is deterministic and evenly spread.
+    pub(crate) fn emit(&mut self, time: Real, dt: Real, spawned: &mut Vec<Vec2>) {
+        if time < self.start || self.stop.is_some_and(|stop| time >= stop) {
+            return;
+        }
+        self.pending += self.rate * dt;
+        let (min, max) = self.shape.bounds();
+        while self.pending >= 1.0 {
+            self.pending -= 1.0;
+            // rejection sampling, bounded so that degenerate shapes cannot stall
+            for _ in 0..32 {
+                self.emitted += 1;
+                let u = Vec2::new(halton(self.emitted, 2), halton(self.emitted, 3));
+                let x = min + u * (max - min);
+                if self.shape.contains(x) {
+                    spawned.push(x);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn halton(mut index: u64, base: u64) -> Real {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as Real;
+        r += f * (index % base) as Real;
+        index /= base;
+    }
+    r
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read scene: {e}"),
+            Self::Parse(e) => write!(f, "failed to parse scene: {e}"),
+            Self::Invalid(e) => write!(f, "invalid scene: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(e) => Some(e),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+impl Scene {
+    /// Parses a scene from TOML source.
+    ///
+    /// # Errors
+    /// Will return `Err` if the source is not a valid scene description.
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        let scene: Self = toml::from_str(source).map_err(SceneError::Parse)?;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Reads and parses a TOML scene file.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file cannot be read or is not a valid scene description.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Self::from_toml(&std::fs::read_to_string(path).map_err(SceneError::Io)?)
+    }
+
+    /// Serializes the scene back to TOML.
+    ///
+    /// # Errors
+    /// Will return `Err` if the scene cannot be represented in TOML.
+    pub fn to_toml(&self) -> Result<String, SceneError> {
+        // going through `toml::Value` emits nested tables in a valid order
+        toml::Value::try_from(self)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(|e| SceneError::Invalid(e.to_string()))
+    }
+
+    fn validate(&self) -> Result<(), SceneError> {
+        let invalid = |msg: String| Err(SceneError::Invalid(msg));
+        // comparisons are written so that NaN values are rejected as well
+        let at_least = |v: Real, min: Real| v >= min;
+        let positive = |v: Real| v > 0.0;
+        let Domain { width, height, .. } = self.domain;
+        validate_domain(Vec2::new(width, height)).map_err(SceneError::Invalid)?;
+        if let Err(e) = self.parameters.validate() {
+            return invalid(e.to_string());
+        }
+        if let Some(duration) = self.duration.filter(|d| !at_least(*d, 0.0)) {
+            return invalid(format!("duration {duration} must not be negative"));
+        }
+        let sources = self.thermal.iter().flat_map(|t| &t.sources);
+        let injectors = self.scalars.iter().flat_map(|s| &s.injectors);
+        let shapes = self
+            .fluids
+            .iter()
+            .map(|f| &f.shape)
+            .chain(self.obstacles.iter().map(|o| &o.shape))
+            .chain(self.emitters.iter().map(|e| &e.shape))
+            .chain(sources.clone().map(|s| &s.shape))
+            .chain(injectors.clone().map(|i| &i.shape));
+        for shape in shapes {
+            match shape {
+                Shape::Rectangle { min, max } if !(min.x < max.x && min.y < max.y) => {
+                    return invalid(format!("rectangle min {min} must be below max {max}"));
+                }
+                Shape::Circle { radius, .. } if !positive(*radius) => {
+                    return invalid(format!("circle radius {radius} must be positive"));
+                }
+                Shape::Polygon { vertices } if vertices.len() < 3 => {
+                    return invalid("polygons need at least three vertices".to_owned());
+                }
+                _ => (),
+            }
+        }
+        if let Some(e) = self.emitters.iter().find(|e| !at_least(e.rate, 0.0)) {
+            return invalid(format!("emitter rate {} must not be negative", e.rate));
+        }
+        if let Some(thermal) = &self.thermal {
+            let rates = [thermal.diffusivity, thermal.wall_conductance]
+                .into_iter()
+                .chain(sources.map(|s| s.rate));
+            if let Some(rate) = rates.into_iter().find(|r| !at_least(*r, 0.0)) {
+                return invalid(format!(
+                    "thermal diffusivity, conductance and rates must not be negative, got {rate}"
+                ));
+            }
+        }
+        self.validate_scalars()
+    }
+
+    fn validate_scalars(&self) -> Result<(), SceneError> {
+        let invalid = |msg: String| Err(SceneError::Invalid(msg));
+        // rejects NaN as well
+        let at_least = |v: Real, min: Real| v >= min;
+        let channels = self.scalars.len();
+        transport::validate_scalars(&self.scalars)
+            .map_err(|e| SceneError::Invalid(e.to_string()))?;
+        for channel in &self.scalars {
+            let name = &channel.name;
+            let rates = std::iter::once(channel.diffusivity)
+                .chain(channel.injectors.iter().map(|i| i.rate));
+            if let Some(rate) = rates.into_iter().find(|r| !at_least(*r, 0.0)) {
+                return invalid(format!(
+                    "scalar {name:?} diffusivity and injector rates must not be negative, got {rate}"
+                ));
+            }
+        }
+        let given = self
+            .fluids
+            .iter()
+            .map(|f| f.concentrations.len())
+            .chain(self.emitters.iter().map(|e| e.concentrations.len()));
+        if let Some(len) = given.into_iter().find(|len| *len > channels) {
+            return invalid(format!(
+                "{len} concentrations given for {channels} scalar channels"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a domain of `size` is at least [`MIN_DOMAIN_SIZE`] in each
+/// direction and finite with at most [`MAX_GRID_CELLS`] grid cells.
+pub(crate) fn validate_domain(size: Vec2) -> Result<(), String> {
+    let Vec2 {
+        x: width,
+        y: height,
+    } = size;
+    // written so that NaN values are rejected as well
+    if !(width >= MIN_DOMAIN_SIZE && height >= MIN_DOMAIN_SIZE) {
+        return Err(format!(
+            "domain {width}x{height} is smaller than {MIN_DOMAIN_SIZE} in some direction"
+        ));
+    }
+    let cells = (size / CELL_SIZE).floor();
+    if cells.x * cells.y > MAX_GRID_CELLS as Real {
+        return Err(format!(
+            "domain {width}x{height} needs more than {MAX_GRID_CELLS} grid cells"
+        ));
+    }
+    Ok(())
+}
+
+impl State {
+    /// Creates a state from a scene description.
+    ///
+    /// # Errors
+    /// Will return `Err` if the scene is invalid or the fluid regions hold more
+    /// than [`MAX_PARTICLES`] particles.
+    pub fn from_scene(scene: &Scene) -> Result<Self, SceneError> {
+        let mut state = Self::new();
+        state.load_scene(scene)?;
+        Ok(state)
+    }
+
+    /// Replaces all particles, obstacles, emitters, parameters and the domain with
+    /// those of `scene`. The thread pool is kept.
+    ///
+    /// # Errors
+    /// Will return `Err` if the scene is invalid, in which case the state is left
+    /// unchanged, or if the fluid regions hold more than [`MAX_PARTICLES`]
+    /// particles, in which case the state is left empty.
+    pub fn load_scene(&mut self, scene: &Scene) -> Result<(), SceneError> {
+        scene.validate()?;
+        self.clear();
+        self.obstacles.clear();
+        self.emitters.clear();
+        self.set_domain(Vec2::new(scene.domain.width, scene.domain.height));
+        self.set_periodic_x(scene.domain.periodic_x);
+        self.set_params(scene.parameters);
+        self.set_thermal(scene.thermal.clone());
+        self.set_scalars(scene.scalars.clone())
+            .map_err(|e| SceneError::Invalid(e.to_string()))?;
+        for obstacle in &scene.obstacles {
+            self.add_obstacle(obstacle.shape.clone());
+        }
+        for emitter in &scene.emitters {
+            self.add_emitter(emitter.clone());
+        }
+        for fluid in &scene.fluids {
+            let Ok(points) = self.fitting_lattice(&fluid.shape) else {
+                self.clear();
+                return Err(SceneError::Invalid(format!(
+                    "fluid regions exceed the maximum of {MAX_PARTICLES} particles"
+                )));
+            };
+            let added = self.fill_points(&points, fluid.velocity);
+            let n = self.particles.len();
+            let c = transport::concentrations(&fluid.concentrations);
+            for p in &mut self.particles[n - added..] {
+                p.t = fluid.temperature.unwrap_or(p.t);
+                p.c = c;
+            }
+        }
+        Ok(())
+    }
+}
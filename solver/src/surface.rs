@@ -0,0 +1,399 @@
+//! Free-surface reconstruction with marching squares.
+//!
+//! An SPH field is sampled from the particles onto a regular grid that extends
+//! one smoothing radius beyond the domain, so the field vanishes on the grid
+//! border and every contour is closed.
+
+use std::collections::HashMap;
+
+use crate::{Real, State, Vec2, H, KERN, MAX_GRID_CELLS, PARTICLE_SPACING};
+
+/// Largest number of cells sampled, about enough for the default cell size on
+/// the largest domain
+const MAX_CELLS: usize = 16 * MAX_GRID_CELLS;
+
+/// Field whose iso-contour is extracted as the fluid surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Normalized color field `sum_j m_j / rho_j W(x - x_j)`, about one inside the fluid
+    Color,
+    /// Mass density `sum_j m_j W(x - x_j)` as computed by the solver
+    Density,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceOptions {
+    pub field: Field,
+    /// Iso-value of the surface, points with larger field values are inside
+    pub threshold: Real,
+    /// Spacing of the sampling grid
+    pub cell_size: Real,
+    /// Also triangulate the enclosed fluid region
+    pub triangulate: bool,
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self {
+        Self {
+            field: Field::Color,
+            threshold: 0.5,
+            cell_size: 0.5 * PARTICLE_SPACING,
+            triangulate: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Surface {
+    /// Closed polylines without repeated end point. Outer boundaries run
+    /// counter-clockwise and holes clockwise, i.e. the fluid is on the left.
+    pub outlines: Vec<Vec<Vec2>>,
+    /// Counter-clockwise triangles covering the fluid, empty unless requested
+    pub triangles: Vec<[Vec2; 3]>,
+}
+
+impl Surface {
+    /// Bounding box of all outlines as `(min, max)`, `None` without fluid.
+    #[must_use]
+    pub fn bounds(&self) -> Option<(Vec2, Vec2)> {
+        self.outlines
+            .iter()
+            .flatten()
+            .fold(None, |bounds, p| match bounds {
+                None => Some((*p, *p)),
+                Some((min, max)) => Some((p.min(min), p.max(max))),
+            })
+    }
+
+    /// Highest point of the surface above `x`, e.g. to measure wave heights.
+    #[must_use]
+    pub fn height_at(&self, x: Real) -> Option<Real> {
+        let mut height: Option<Real> = None;
+        for outline in &self.outlines {
+            let mut j = outline.len() - 1;
+            for (i, a) in outline.iter().enumerate() {
+                let b = outline[j];
+                if (a.x <= x) != (b.x <= x) {
+                    let y = a.y + (x - a.x) / (b.x - a.x) * (b.y - a.y);
+                    height = Some(height.map_or(y, |h| h.max(y)));
+                }
+                j = i;
+            }
+        }
+        height
+    }
+}
+
+/// Field values sampled on the nodes of a regular grid.
+#[derive(Debug, Clone)]
+pub struct ScalarGrid {
+    origin: Vec2,
+    cell_size: Real,
+    nx: usize,
+    ny: usize,
+    values: Vec<Real>,
+}
+
+impl ScalarGrid {
+    /// Splats `field` of all particles onto a grid covering the domain plus a
+    /// margin of one smoothing radius. The cell size is enlarged where needed to
+    /// stay within about [`MAX_CELLS`] cells.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is not positive.
+    #[must_use]
+    pub fn sample(state: &State, field: Field, cell_size: Real) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        let origin = Vec2::splat(-H);
+        let extent = state.domain() + 2.0 * H;
+        let cell_size = cell_size.max((extent.x * extent.y / MAX_CELLS as Real).sqrt());
+        let nx = (extent.x / cell_size).ceil() as usize + 1;
+        let ny = (extent.y / cell_size).ceil() as usize + 1;
+        let mut values = vec![0.0; nx * ny];
+        let rest_density = state.params().rest_density;
+        for p in &state.particles {
+            let weight = match field {
+                Field::Density => p.m,
+                // fall back to the rest density before the first solver step
+                Field::Color => p.m / if p.d > 0.0 { p.d } else { p.m * rest_density },
+            };
+            let min = ((p.x - H - origin) / cell_size).ceil().max(Vec2::ZERO);
+            let max = ((p.x + H - origin) / cell_size).floor();
+            let (x0, y0) = (min.x as usize, min.y as usize);
+            let x1 = (max.x.max(0.0) as usize).min(nx - 1);
+            let y1 = (max.y.max(0.0) as usize).min(ny - 1);
+            for j in y0..=y1 {
+                for i in x0..=x1 {
+                    let node = origin + Vec2::new(i as Real, j as Real) * cell_size;
+                    let r = node.distance(p.x);
+                    if r < H {
+                        let a = 1.0 - r / H;
+                        values[i + j * nx] += weight * a * a * a * KERN;
+                    }
+                }
+            }
+        }
+        Self {
+            origin,
+            cell_size,
+            nx,
+            ny,
+            values,
+        }
+    }
+
+    /// Number of nodes along x and y.
+    #[must_use]
+    pub fn size(&self) -> (usize, usize) {
+        (self.nx, self.ny)
+    }
+
+    #[must_use]
+    pub fn value(&self, i: usize, j: usize) -> Real {
+        self.values[i + j * self.nx]
+    }
+
+    #[must_use]
+    pub fn position(&self, i: usize, j: usize) -> Vec2 {
+        self.origin + Vec2::new(i as Real, j as Real) * self.cell_size
+    }
+
+    /// Closed iso-contours at `threshold`, oriented with larger values on the left.
+    #[must_use]
+    pub fn contours(&self, threshold: Real) -> Vec<Vec<Vec2>> {
+        // each segment runs from the crossing where a counter-clockwise walk around
+        // its cell leaves the inside to the next one where it enters again, so every
+        // crossing starts exactly one segment and ends exactly one segment
+        let mut next = HashMap::new();
+        let mut points = HashMap::new();
+        for j in 0..self.ny - 1 {
+            for i in 0..self.nx - 1 {
+                let crossings = self.cell_crossings(i, j, threshold);
+                let pairs: &[(usize, usize)] = match crossings.len() {
+                    2 => &[(0, 1)],
+                    // saddle, resolved by the average of the corners
+                    4 if self.cell_center(i, j) >= threshold => &[(0, 1), (2, 3)],
+                    4 => &[(0, 3), (2, 1)],
+                    _ => &[],
+                };
+                for &(exit, entry) in pairs {
+                    let (exit, entry) = (crossings[exit], crossings[entry]);
+                    next.insert(exit.0, entry.0);
+                    points.insert(exit.0, exit.1);
+                    points.insert(entry.0, entry.1);
+                }
+            }
+        }
+
+        let mut keys: Vec<usize> = next.keys().copied().collect();
+        keys.sort_unstable();
+        let mut contours = Vec::new();
+        for start in keys {
+            let mut key = start;
+            let mut contour = Vec::new();
+            while let Some(following) = next.remove(&key) {
+                contour.push(points[&key]);
+                key = following;
+            }
+            if !contour.is_empty() {
+                contours.push(contour);
+            }
+        }
+        contours
+    }
+
+    /// Counter-clockwise triangles covering the region with values of at least `threshold`.
+    #[must_use]
+    pub fn triangulate(&self, threshold: Real) -> Vec<[Vec2; 3]> {
+        let mut triangles = Vec::new();
+        let mut polygon = Vec::with_capacity(8);
+        for j in 0..self.ny - 1 {
+            for i in 0..self.nx - 1 {
+                let corners = self.cell_corners(i, j);
+                let inside = corners.map(|(_, v)| v >= threshold);
+                if !inside.contains(&true) {
+                    continue;
+                }
+                let saddle =
+                    inside == [true, false, true, false] || inside == [false, true, false, true];
+                let split = saddle && self.cell_center(i, j) < threshold;
+                polygon.clear();
+                for k in 0..4 {
+                    let (a, b) = (corners[k], corners[(k + 1) % 4]);
+                    if inside[k] {
+                        polygon.push(a.0);
+                    }
+                    if inside[k] != inside[(k + 1) % 4] {
+                        polygon.push(interpolate(a, b, threshold));
+                    }
+                }
+                if split {
+                    // two separate corner triangles, each made of an inside corner
+                    // and the crossings on either side of it
+                    let first = if inside[0] { 5 } else { 0 };
+                    for k in [first, (first + 3) % 6] {
+                        triangles.push([polygon[k], polygon[(k + 1) % 6], polygon[(k + 2) % 6]]);
+                    }
+                } else {
+                    for k in 1..polygon.len() - 1 {
+                        triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    /// Corners of cell `(i, j)` with their values, counter-clockwise from the bottom-left.
+    fn cell_corners(&self, i: usize, j: usize) -> [(Vec2, Real); 4] {
+        [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)]
+            .map(|(i, j)| (self.position(i, j), self.value(i, j)))
+    }
+
+    fn cell_center(&self, i: usize, j: usize) -> Real {
+        self.cell_corners(i, j).iter().map(|(_, v)| v).sum::<Real>() / 4.0
+    }
+
+    /// Iso-crossings on the edges of cell `(i, j)` in counter-clockwise order, each
+    /// keyed by its grid edge. Exits from the inside and entries alternate, starting
+    /// with an exit.
+    fn cell_crossings(&self, i: usize, j: usize, threshold: Real) -> Vec<(usize, Vec2)> {
+        let corners = self.cell_corners(i, j);
+        // horizontal edges have even keys, vertical edges odd keys
+        let edges = [
+            2 * (i + j * self.nx),
+            2 * (i + 1 + j * self.nx) + 1,
+            2 * (i + (j + 1) * self.nx),
+            2 * (i + j * self.nx) + 1,
+        ];
+        let mut crossings = Vec::with_capacity(4);
+        for k in 0..4 {
+            let (a, b) = (corners[k], corners[(k + 1) % 4]);
+            if (a.1 >= threshold) != (b.1 >= threshold) {
+                crossings.push((edges[k], interpolate(a, b, threshold), a.1 >= threshold));
+            }
+        }
+        // rotate so that the first crossing leaves the inside
+        if let Some(first_exit) = crossings.iter().position(|c| c.2) {
+            crossings.rotate_left(first_exit);
+        }
+        crossings.into_iter().map(|(key, p, _)| (key, p)).collect()
+    }
+}
+
+fn interpolate((a, va): (Vec2, Real), (b, vb): (Vec2, Real), threshold: Real) -> Vec2 {
+    a + (b - a) * ((threshold - va) / (vb - va))
+}
+
+impl State {
+    /// Reconstructs the free surface of the fluid from the current particles.
+    #[must_use]
+    pub fn surface(&self, options: &SurfaceOptions) -> Surface {
+        let grid = ScalarGrid::sample(self, options.field, options.cell_size);
+        Surface {
+            outlines: grid.contours(options.threshold),
+            triangles: if options.triangulate {
+                grid.triangulate(options.threshold)
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    /// Grid of `nx` by `ny` nodes with unit cells starting at the origin.
+    fn grid(nx: usize, ny: usize, f: impl Fn(Vec2) -> Real) -> ScalarGrid {
+        let mut grid = ScalarGrid {
+            origin: Vec2::ZERO,
+            cell_size: 1.0,
+            nx,
+            ny,
+            values: vec![0.0; nx * ny],
+        };
+        for j in 0..ny {
+            for i in 0..nx {
+                grid.values[i + j * nx] = f(grid.position(i, j));
+            }
+        }
+        grid
+    }
+
+    /// Signed area, positive for counter-clockwise polygons.
+    fn area(polygon: &[Vec2]) -> f64 {
+        let mut j = polygon.len() - 1;
+        let mut area = 0.0;
+        for (i, a) in polygon.iter().enumerate() {
+            area += crate::to_f64(polygon[j].perp_dot(*a));
+            j = i;
+        }
+        0.5 * area
+    }
+
+    fn triangle_areas(triangles: &[[Vec2; 3]]) -> Vec<f64> {
+        triangles.iter().map(|t| area(t)).collect()
+    }
+
+    fn circle() -> ScalarGrid {
+        let center = Vec2::splat(10.0);
+        grid(21, 21, |p| 64.0 - p.distance_squared(center))
+    }
+
+    #[test]
+    fn circle_contour_runs_counter_clockwise_around_its_area() {
+        let contours = circle().contours(0.0);
+        assert_eq!(contours.len(), 1);
+        // chords cut off a little of the circle of radius 8
+        let expected = PI * 64.0;
+        let area = area(&contours[0]);
+        assert!(area > 0.0);
+        assert!((area - expected).abs() < 0.01 * expected, "{area}");
+    }
+
+    #[test]
+    fn triangles_cover_the_enclosed_area() {
+        let grid = circle();
+        let enclosed: f64 = grid.contours(0.0).iter().map(|c| area(c)).sum();
+        let areas = triangle_areas(&grid.triangulate(0.0));
+        assert!(areas.iter().all(|&a| a >= 0.0));
+        let covered: f64 = areas.iter().sum();
+        assert!((covered - enclosed).abs() < 1e-3, "{covered} != {enclosed}");
+    }
+
+    #[test]
+    fn saddles_are_resolved_by_the_cell_average() {
+        // diagonal nodes (1, 1) and (2, 2) are inside, the centre cell is a saddle
+        // whose corners average 0.5
+        let saddle = grid(4, 4, |p| {
+            if p == Vec2::new(1.0, 1.0) || p == Vec2::new(2.0, 2.0) {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        for (threshold, expected) in [(0.6, vec![0.32, 0.32]), (0.4, vec![1.92])] {
+            let contours = saddle.contours(threshold);
+            let areas: Vec<f64> = contours.iter().map(|c| area(c)).collect();
+            assert_eq!(areas.len(), expected.len(), "threshold {threshold}");
+            for (area, expected) in areas.iter().zip(&expected) {
+                assert!((area - expected).abs() < 1e-5, "{area} != {expected}");
+            }
+            let covered: f64 = triangle_areas(&saddle.triangulate(threshold)).iter().sum();
+            let enclosed: f64 = expected.iter().sum();
+            assert!((covered - enclosed).abs() < 1e-5, "{covered} != {enclosed}");
+        }
+    }
+
+    #[test]
+    fn sampling_bounds_the_cell_count() {
+        let grid = ScalarGrid::sample(&State::new(), Field::Color, 1e-6);
+        let (nx, ny) = grid.size();
+        // rounding up to whole cells adds at most a row and a column
+        assert!((nx - 1) * (ny - 1) <= MAX_CELLS + nx + ny, "{nx} x {ny}");
+    }
+}
@@ -0,0 +1,139 @@
+//! Optional heat transfer: SPH diffusion of a per-particle temperature, heated or
+//! cooled walls and sources, Boussinesq buoyancy and temperature-dependent viscosity.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{Particle, Real, Shape, State, H, KERN};
+
+/// Heat transfer settings, see [`State::set_thermal`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thermal {
+    /// Temperature at which the fluid has its nominal weight and viscosity, and
+    /// of newly placed particles
+    pub reference_temperature: Real,
+    /// Thermal diffusivity in squared length units per second
+    pub diffusivity: Real,
+    /// Boussinesq expansion coefficient, gravity acting on a particle is scaled by
+    /// `1 - expansion * (T - reference_temperature)`
+    pub expansion: Real,
+    /// Viscosity is scaled by `exp(-viscosity_falloff * (T - reference_temperature))`,
+    /// so positive values make hot fluid runny and cold fluid sluggish
+    pub viscosity_falloff: Real,
+    /// Temperatures of the domain walls
+    pub walls: WallTemperatures,
+    /// Rate per second at which particles touching a wall with a temperature
+    /// approach it
+    pub wall_conductance: Real,
+    #[serde(rename = "source")]
+    pub sources: Vec<HeatSource>,
+}
+
+impl Default for Thermal {
+    fn default() -> Self {
+        Self {
+            reference_temperature: 0.0,
+            diffusivity: 0.01,
+            expansion: 0.0,
+            viscosity_falloff: 0.0,
+            walls: WallTemperatures::default(),
+            wall_conductance: 5.0,
+            sources: Vec::new(),
+        }
+    }
+}
+
+/// Fixed temperature of each domain wall, `None` for insulating walls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallTemperatures {
+    pub left: Option<Real>,
+    pub bottom: Option<Real>,
+    pub right: Option<Real>,
+    pub top: Option<Real>,
+}
+
+impl WallTemperatures {
+    /// Temperatures in the order of the domain boundaries.
+    pub(crate) fn to_array(self) -> [Option<Real>; 4] {
+        [self.left, self.bottom, self.right, self.top]
+    }
+}
+
+/// Region that heats or cools the particles within a smoothing radius of it,
+/// e.g. an obstacle with the same shape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeatSource {
+    pub shape: Shape,
+    pub temperature: Real,
+    /// Rate per second at which touching particles approach `temperature`
+    pub rate: Real,
+}
+
+impl State {
+    #[must_use]
+    pub fn thermal(&self) -> Option<&Thermal> {
+        self.thermal.as_ref()
+    }
+
+    /// Enables heat transfer with the given settings, or disables it with `None`.
+    /// Particle temperatures are kept either way.
+    pub fn set_thermal(&mut self, thermal: Option<Thermal>) {
+        self.thermal = thermal;
+    }
+
+    /// Diffuses temperature between neighbors and exchanges heat with walls and
+    /// sources. The densities of the last force computation serve as particle
+    /// volumes, and `particles_initial` holds the temperatures before this step.
+    pub(crate) fn transfer_heat(&mut self) {
+        let Some(thermal) = &self.thermal else {
+            return;
+        };
+        let dt = self.params.dt();
+        let periodic = self.periodic_x;
+        let walls: Vec<_> = (self.boundaries.iter())
+            .zip(thermal.walls.to_array())
+            .filter(|(b, _)| !periodic || b.x == 0.0)
+            .filter_map(|(b, temperature)| Some((*b, temperature?)))
+            .collect();
+        // approaching the target at most fully keeps explicit steps stable
+        let relax = |rate: Real, d: Real| (dt * rate * (1.0 - d.max(0.0) / H)).min(1.0);
+        self.particles
+            .par_iter_mut()
+            .zip_eq(self.neighborhoods.par_iter())
+            .for_each(|(pi, ni)| {
+                let mut laplacian = 0.0;
+                for neighbor in ni {
+                    let pj = &self.particles_initial[neighbor.index];
+                    laplacian += diffusion_weight(pj, neighbor.r) * (pj.t - pi.t);
+                }
+                pi.t += dt * thermal.diffusivity * laplacian;
+
+                for (b, temperature) in &walls {
+                    let d = pi.x.x * b.x + pi.x.y * b.y - b.z;
+                    if d < H {
+                        pi.t += relax(thermal.wall_conductance, d) * (temperature - pi.t);
+                    }
+                }
+                for source in &thermal.sources {
+                    let (d, _) = source.shape.signed_distance(pi.x);
+                    if d < H {
+                        pi.t += relax(source.rate, d) * (source.temperature - pi.t);
+                    }
+                }
+            });
+    }
+}
+
+/// Weight of neighbor `pj` at distance `r` in the SPH Laplacian of a diffused
+/// quantity, `2 m_j / ρ_j |∇W| / r`. Particles without a density yet, i.e.
+/// emitted since the last force computation, are skipped.
+pub(crate) fn diffusion_weight(pj: &Particle, r: Real) -> Real {
+    if pj.d <= 0.0 {
+        return 0.0;
+    }
+    let a = 1.0 - r / H;
+    2.0 * pj.m / pj.d * 3.0 * a * a * KERN / (H * r)
+}
@@ -0,0 +1,140 @@
+//! Passive scalar channels such as dye, salinity or pollutants, carried along
+//! with the particles, diffused between neighbors and added by injectors.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::thermal::diffusion_weight;
+use crate::{Real, Shape, SolverError, State, MAX_SCALARS};
+
+/// Settings of one scalar channel, see [`State::set_scalars`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScalarChannel {
+    /// Name used by exports, e.g. `"dye"`
+    pub name: String,
+    /// Diffusion coefficient in squared length units per second, zero for pure
+    /// advection
+    pub diffusivity: Real,
+    /// RGB color of the fully concentrated scalar in renderings
+    pub color: [f32; 3],
+    #[serde(rename = "injector")]
+    pub injectors: Vec<Injector>,
+}
+
+impl Default for ScalarChannel {
+    fn default() -> Self {
+        Self {
+            name: "dye".to_owned(),
+            diffusivity: 0.001,
+            color: [0.9, 0.2, 0.2],
+            injectors: Vec::new(),
+        }
+    }
+}
+
+/// Region that raises or lowers the concentration of the particles inside it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Injector {
+    pub shape: Shape,
+    pub concentration: Real,
+    /// Rate per second at which particles inside approach `concentration`
+    pub rate: Real,
+}
+
+impl State {
+    #[must_use]
+    pub fn scalars(&self) -> &[ScalarChannel] {
+        &self.scalars
+    }
+
+    /// Replaces the scalar channels, an empty list disables transport. Channel
+    /// `i` is stored in [`crate::Particle::concentration`]`(i)` of every particle,
+    /// and concentrations are kept when channels change.
+    ///
+    /// # Errors
+    /// Will return `Err` if more than [`MAX_SCALARS`] channels are given or their
+    /// names, which become export array names, are not unique ASCII identifiers or
+    /// clash with a built-in array such as `pressure`. The channels are then left
+    /// unchanged.
+    pub fn set_scalars(&mut self, scalars: Vec<ScalarChannel>) -> Result<(), SolverError> {
+        validate_scalars(&scalars)?;
+        self.scalars = scalars;
+        Ok(())
+    }
+
+    /// Diffuses the concentrations between neighbors and applies the injectors,
+    /// like `transfer_heat` does for the temperature.
+    pub(crate) fn transport_scalars(&mut self) {
+        let dt = self.params.dt();
+        let scalars = &self.scalars;
+        self.particles
+            .par_iter_mut()
+            .zip_eq(self.neighborhoods.par_iter())
+            .for_each(|(pi, ni)| {
+                let mut laplacian = [0.0; MAX_SCALARS];
+                for neighbor in ni {
+                    let pj = &self.particles_initial[neighbor.index];
+                    let weight = diffusion_weight(pj, neighbor.r);
+                    for (k, l) in laplacian.iter_mut().enumerate().take(scalars.len()) {
+                        *l += weight * (pj.c[k] - pi.c[k]);
+                    }
+                }
+                for (k, channel) in scalars.iter().enumerate() {
+                    let c = &mut pi.c[k];
+                    *c += dt * channel.diffusivity * laplacian[k];
+                    for injector in &channel.injectors {
+                        if injector.shape.contains(pi.x) {
+                            // approaching the target at most fully keeps explicit steps stable
+                            *c += (dt * injector.rate).min(1.0) * (injector.concentration - *c);
+                        }
+                    }
+                }
+            });
+    }
+}
+
+/// Names of the built-in VTK point arrays, which scalar channels must not reuse
+const BUILT_IN_ARRAYS: [&str; 8] = [
+    "velocity",
+    "pressure",
+    "near_pressure",
+    "density",
+    "mass",
+    "temperature",
+    "vorticity",
+    "position",
+];
+
+/// Checks the channel count and that the names, which become export array
+/// names, are unique ASCII identifiers other than the built-in array names.
+pub(crate) fn validate_scalars(scalars: &[ScalarChannel]) -> Result<(), SolverError> {
+    if scalars.len() > MAX_SCALARS {
+        return Err(SolverError::TooManyScalars {
+            requested: scalars.len(),
+        });
+    }
+    for (i, channel) in scalars.iter().enumerate() {
+        let name = &channel.name;
+        let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !identifier || BUILT_IN_ARRAYS.contains(&name.as_str()) {
+            return Err(SolverError::InvalidScalarName { name: name.clone() });
+        }
+        if scalars[..i].iter().any(|other| &other.name == name) {
+            return Err(SolverError::DuplicateScalarName { name: name.clone() });
+        }
+    }
+    Ok(())
+}
+
+/// Concentrations of all channels from those given for the first ones, e.g. in
+/// a scene, with the remaining channels at zero.
+pub(crate) fn concentrations(given: &[Real]) -> [Real; MAX_SCALARS] {
+    let mut c = [0.0; MAX_SCALARS];
+    for (c, given) in c.iter_mut().zip(given) {
+        *c = *given;
+    }
+    c
+}
@@ -0,0 +1,99 @@
+//! Vorticity estimation, vorticity confinement and XSPH velocity smoothing.
+
+use rayon::prelude::*;
+
+use crate::{minimum_image, Particle, Real, State, Vec2, EPS, H, KERN};
+
+impl State {
+    /// Vorticity `dv_y/dx - dv_x/dy` of each particle, estimated from the current
+    /// velocities over the neighborhoods of the last solver step.
+    ///
+    /// The kernel gradient sum is normalized by its moment `Σ V_j dx·∇W`, which is
+    /// two for a full neighborhood, so that linear velocity fields such as a rigid
+    /// rotation are reproduced exactly.
+    #[must_use]
+    pub fn vorticity(&self) -> Vec<Real> {
+        self.install(|| {
+            (0..self.particles.len())
+                .into_par_iter()
+                .map(|i| {
+                    let pi = &self.particles[i];
+                    let (mut curl, mut moment) = (0.0, 0.0);
+                    for (_, pj, volume, dx, r) in self.neighbors(i) {
+                        let a = 1.0 - r / H;
+                        // gradient of the density kernel with respect to pi.x
+                        let grad = 3.0 * KERN * a * a / H * dx / r;
+                        let dv = pj.v - pi.v;
+                        curl += volume * (dv.y * grad.x - dv.x * grad.y);
+                        moment += volume * dx.dot(grad);
+                    }
+                    if moment > EPS {
+                        2.0 * curl / moment
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Applies vorticity confinement and XSPH smoothing to the velocities of the
+    /// last projection, which the next solver step integrates.
+    ///
+    /// Confinement accelerates each particle by `ε H (N × ω)`, where `N` points
+    /// towards increasing vorticity magnitude, which spins up existing eddies
+    /// instead of creating new ones. XSPH blends each velocity towards the
+    /// kernel-weighted velocity of its neighbors.
+    pub(crate) fn refine_velocities(&mut self) {
+        let vorticity = self.vorticity();
+        let dt = self.params.dt();
+        let (confinement, xsph) = (self.params.vorticity_confinement, self.params.xsph);
+        let corrections: Vec<Vec2> = (0..self.particles.len())
+            .into_par_iter()
+            .map(|i| {
+                let pi = &self.particles[i];
+                let wi = vorticity[i];
+                let mut smoothing = Vec2::ZERO;
+                let mut gradient = Vec2::ZERO;
+                for (j, pj, volume, dx, r) in self.neighbors(i) {
+                    let a = 1.0 - r / H;
+                    smoothing += volume * a * a * a * KERN * (pj.v - pi.v);
+                    let grad = 3.0 * KERN * a * a / H * dx / r;
+                    gradient += volume * (vorticity[j].abs() - wi.abs()) * grad;
+                }
+                let mut dv = xsph * smoothing;
+                let length = gradient.length();
+                if confinement > 0.0 && length > EPS {
+                    let n = gradient / length;
+                    dv += dt * confinement * H * wi * Vec2::new(n.y, -n.x);
+                }
+                dv
+            })
+            .collect();
+        self.particles
+            .par_iter_mut()
+            .zip_eq(corrections)
+            .for_each(|(p, dv)| p.v += dv);
+    }
+
+    /// Neighbors of particle `i` in the last solver step that have a density,
+    /// with their index, volume, current offset from `i` and distance, while still
+    /// within the smoothing radius.
+    ///
+    /// Volumes are capped at the rest volume, as sparse particles in splashes have
+    /// next to no density and would otherwise dominate the estimates.
+    fn neighbors(&self, i: usize) -> impl Iterator<Item = (usize, &Particle, Real, Vec2, Real)> {
+        let period = self.period_x();
+        let rest_density = self.params.rest_density;
+        let pi = &self.particles[i];
+        (self.neighborhoods.get(i).into_iter().flatten())
+            .filter_map(|n| Some((n.index, self.particles.get(n.index)?)))
+            .filter(|(_, pj)| pj.d > 0.0)
+            .filter_map(move |(j, pj)| {
+                let dx = minimum_image(pj.x - pi.x, period);
+                let r = dx.length();
+                let volume = pj.m / pj.d.max(pj.m * rest_density);
+                (EPS..H).contains(&r).then_some((j, pj, volume, dx, r))
+            })
+    }
+}
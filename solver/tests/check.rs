@@ -0,0 +1,68 @@
+//! Validation of the particle state by `State::check` and of fallible setters.
+
+use solver::{Shape, SolverError, State, Vec2};
+
+fn dam_break() -> State {
+    let mut state = State::new();
+    state.try_init_dam_break(10 * 10).unwrap();
+    state
+}
+
+#[test]
+fn reports_particles_just_outside_the_domain() {
+    let mut state = dam_break();
+    let domain = state.domain();
+    // splashes overshoot the soft walls a little
+    state.particles[0].x = Vec2::new(0.5 * domain.x, domain.y + 0.3);
+    assert_eq!(state.check(), Ok(()));
+
+    let position = Vec2::new(-1.0, 0.5 * domain.y);
+    state.particles[3].x = position;
+    assert_eq!(
+        state.check(),
+        Err(SolverError::OutOfDomain { index: 3, position })
+    );
+}
+
+#[test]
+fn ignores_the_periodic_axis() {
+    let mut state = dam_break();
+    state.set_periodic_x(true);
+    let domain = state.domain();
+    state.particles[0].x = Vec2::new(domain.x + 1.0, 0.5 * domain.y);
+    assert_eq!(state.check(), Ok(()));
+    state.particles[0].x = Vec2::new(0.5 * domain.x, domain.y + 1.0);
+    assert!(matches!(
+        state.check(),
+        Err(SolverError::OutOfDomain { index: 0, .. })
+    ));
+}
+
+#[test]
+fn rejects_oversized_fills_without_adding_particles() {
+    let mut state = dam_break();
+    let shape = Shape::Rectangle {
+        min: Vec2::ZERO,
+        max: Vec2::splat(1e6),
+    };
+    assert!(matches!(
+        state.try_fill_shape(&shape, Vec2::ZERO),
+        Err(SolverError::CapacityExceeded { .. })
+    ));
+    assert_eq!(state.particles.len(), 100);
+}
+
+#[test]
+fn keeps_parameters_on_invalid_input() {
+    let mut state = dam_break();
+    let mut params = *state.params();
+    params.substeps = 0;
+    assert!(matches!(
+        state.try_set_params(params),
+        Err(SolverError::InvalidParameter {
+            name: "substeps",
+            ..
+        })
+    ));
+    assert_ne!(state.params().substeps, 0);
+}
@@ -0,0 +1,117 @@
+//! Checkpoint round trips, which must continue exactly like an uninterrupted run.
+
+use std::io;
+
+use solver::{Particle, Real, Scene, State};
+
+const DAM_PARTICLES: usize = 20 * 20;
+
+fn restored(state: &State) -> State {
+    let mut bytes = Vec::new();
+    state.save_checkpoint(&mut bytes).unwrap();
+    let mut restored = State::new();
+    restored.load_checkpoint(bytes.as_slice()).unwrap();
+    restored
+}
+
+/// Checks that both runs have the same particles, bit for bit.
+fn assert_identical(a: &State, b: &State) {
+    let bits = |p: &Particle| {
+        let (x, v) = (p.x, p.velocity());
+        [
+            x.x,
+            x.y,
+            v.x,
+            v.y,
+            p.pressure(),
+            p.near_pressure(),
+            p.density(),
+            p.temperature(),
+            p.concentration(0),
+            p.concentration(1),
+        ]
+        .map(Real::to_bits)
+    };
+    assert_eq!(a.time().to_bits(), b.time().to_bits());
+    assert_eq!(a.particles.len(), b.particles.len());
+    for (pa, pb) in a.particles.iter().zip(&b.particles) {
+        assert_eq!(pa.id(), pb.id());
+        assert_eq!(bits(pa), bits(pb), "particle {} diverged", pa.id());
+    }
+}
+
+#[test]
+fn continues_at_a_fractional_time_scale() {
+    let mut state = State::new();
+    state.try_init_dam_break(DAM_PARTICLES).unwrap();
+    state.set_time_scale(0.25);
+    // an odd number of frames leaves half a solver step pending
+    for _ in 0..3 {
+        state.update();
+    }
+    let mut resumed = restored(&state);
+    assert_eq!(resumed.time_scale(), 0.25);
+    for _ in 0..5 {
+        state.update();
+        resumed.update();
+    }
+    assert_identical(&resumed, &state);
+}
+
+#[test]
+fn stays_paused() {
+    let mut state = State::new();
+    state.try_init_dam_break(DAM_PARTICLES).unwrap();
+    state.update();
+    state.set_paused(true);
+    let mut resumed = restored(&state);
+    let time: Real = resumed.time();
+    resumed.update();
+    assert!(resumed.paused());
+    assert_eq!(resumed.time(), time);
+}
+
+#[test]
+fn continues_like_an_uninterrupted_run() {
+    let scene = Scene::from_toml(include_str!("../../scenes/mixing.toml")).unwrap();
+    let mut state = State::from_scene(&scene).unwrap();
+    for _ in 0..3 {
+        state.update();
+    }
+    let mut resumed = restored(&state);
+    assert_identical(&resumed, &state);
+    for _ in 0..3 {
+        state.update();
+        resumed.update();
+    }
+    assert_identical(&resumed, &state);
+}
+
+#[test]
+fn rejects_invalid_domains() {
+    let mut state = State::new();
+    state.try_init_dam_break(DAM_PARTICLES).unwrap();
+    let mut bytes = Vec::new();
+    state.save_checkpoint(&mut bytes).unwrap();
+    let domain = state.domain();
+    let stored: Vec<u8> = [domain.x, domain.y]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    let offset = bytes
+        .windows(stored.len())
+        .position(|w| w == stored)
+        .unwrap();
+    for size in [0.0, Real::NAN, Real::INFINITY, 1e6] {
+        let mut corrupt = bytes.clone();
+        corrupt[offset..offset + stored.len() / 2].copy_from_slice(&size.to_le_bytes());
+        let mut loaded = State::new();
+        let error = loaded.load_checkpoint(corrupt.as_slice()).unwrap_err();
+        assert_eq!(
+            error.kind(),
+            io::ErrorKind::InvalidData,
+            "domain width {size}"
+        );
+        assert!(loaded.particles.is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+//! Dam-break validation against the experiments of Martin & Moyce (1952), "An
+//! experimental study of the collapse of liquid columns on a rigid horizontal
+//! plane", Phil. Trans. R. Soc. Lond. A 244, 312-324.
+//!
+//! A square column of water with side `a` collapses next to a wall. The
+//! experiment used a 2.25 in (0.057 m) column, less than one particle at the
+//! fixed spacing of 0.09, so the acceptance criterion is agreement at Froude
+//! similarity: the column is 40 x 40 particles, `a = 3.6` in solver units taken
+//! as meters under standard gravity, and lengths relative to `a` and times in
+//! the dimensionless units of the paper are compared with its curves, which are
+//! the same for both columns. The tolerances are given in the reading intervals
+//! of these curves. Run with `--nocapture` to print the simulated and measured
+//! curves.
+
+use solver::surface::{Surface, SurfaceOptions};
+use solver::{Real, Shape, State, Vec2};
+
+/// Lattice spacing used by [`State::fill_shape`]
+const SPACING: Real = 0.09;
+const COLUMN_PARTICLES: usize = 40;
+const GRAVITY: Real = 9.81;
+
+/// Surge front `x / a` against `T = t sqrt(2g/a)` for the square column (n² = 1)
+const SURGE_FRONT: [(Real, Real); 14] = [
+    (0.41, 1.11),
+    (0.84, 1.22),
+    (1.19, 1.44),
+    (1.43, 1.67),
+    (1.63, 1.89),
+    (1.83, 2.11),
+    (1.98, 2.33),
+    (2.20, 2.56),
+    (2.32, 2.78),
+    (2.51, 3.00),
+    (2.65, 3.22),
+    (2.83, 3.44),
+    (2.98, 3.67),
+    (3.11, 3.89),
+];
+
+/// Residual column height `h / a` at the wall against `T = t sqrt(g/a)` for the
+/// square column, during the early collapse
+const RESIDUAL_HEIGHT: [(Real, Real); 7] = [
+    (0.0, 1.0),
+    (0.56, 0.94),
+    (0.77, 0.89),
+    (0.97, 0.83),
+    (1.16, 0.78),
+    (1.32, 0.72),
+    (1.46, 0.67),
+];
+
+/// Side of the experimental column in inches
+const EXPERIMENT_COLUMN: Real = 2.25;
+/// Tolerance of the surge front `x / a`, two of the half-inch stations at which
+/// the front was timed. The simulated front leads early on and lags late, by up to
+/// about 1.8 stations.
+const FRONT_TOLERANCE: Real = 2.0 * 0.5 / EXPERIMENT_COLUMN;
+/// Tolerance of the residual height `h / a`, one and a half of the eighth-inch
+/// steps in which the height was read. The simulated column falls slightly
+/// faster at first and then lags, by up to 1.3 steps.
+const HEIGHT_TOLERANCE: Real = 1.5 * 0.125 / EXPERIMENT_COLUMN;
+
+/// Signed area of a closed outline, positive for counter-clockwise ones.
+fn area(outline: &[Vec2]) -> Real {
+    let mut j = outline.len() - 1;
+    let mut sum = 0.0;
+    for (i, p) in outline.iter().enumerate() {
+        sum += outline[j].perp_dot(*p);
+        j = i;
+    }
+    0.5 * sum
+}
+
+/// Surface of the main body of fluid, ignoring splashes and holes.
+fn main_body(state: &State) -> Surface {
+    let surface = state.surface(&SurfaceOptions::default());
+    let outline = surface
+        .outlines
+        .into_iter()
+        .max_by(|a, b| area(a).total_cmp(&area(b)))
+        .expect("the fluid vanished");
+    Surface {
+        outlines: vec![outline],
+        triangles: Vec::new(),
+    }
+}
+
+/// Linear interpolation of the samples `(t, value)` sorted by `t`.
+fn interpolate(samples: &[(Real, Real)], t: Real) -> Real {
+    let i = samples
+        .partition_point(|s| s.0 < t)
+        .clamp(1, samples.len() - 1);
+    let ((t0, v0), (t1, v1)) = (samples[i - 1], samples[i]);
+    v0 + (t - t0) / (t1 - t0) * (v1 - v0)
+}
+
+#[test]
+fn dam_break_matches_martin_and_moyce() {
+    let a = COLUMN_PARTICLES as Real * SPACING;
+    let mut state = State::new();
+    state.set_domain(Vec2::new(6.0 * a, 1.5 * a));
+    let column = Shape::Rectangle {
+        min: Vec2::splat(0.5 * SPACING),
+        max: Vec2::splat(a - 0.25 * SPACING),
+    };
+    assert_eq!(
+        state.fill_shape(&column, Vec2::ZERO),
+        COLUMN_PARTICLES * COLUMN_PARTICLES
+    );
+
+    let front_time = (2.0 * GRAVITY / a).sqrt();
+    let height_time = (GRAVITY / a).sqrt();
+    let end = SURGE_FRONT[SURGE_FRONT.len() - 1].0 / front_time;
+    let mut fronts = Vec::new();
+    let mut heights = Vec::new();
+    loop {
+        let t = state.time();
+        let surface = main_body(&state);
+        let (_, max) = surface.bounds().expect("outline without points");
+        fronts.push((t * front_time, max.x / a));
+        // measured one particle spacing from the wall, where the surface is resolved
+        let height = surface
+            .height_at(SPACING)
+            .expect("the column left the wall");
+        heights.push((t * height_time, height / a));
+        if t > end {
+            break;
+        }
+        state.update();
+    }
+
+    let mut failures = Vec::new();
+    println!("     T   front   measured");
+    for (t, measured) in SURGE_FRONT {
+        let simulated = interpolate(&fronts, t);
+        println!("{t:6.2}  {simulated:6.2}  {measured:6.2}");
+        if (simulated - measured).abs() > FRONT_TOLERANCE {
+            failures.push(format!(
+                "front {simulated:.2} at T = {t}, measured {measured}"
+            ));
+        }
+    }
+    println!("     T  height   measured");
+    for (t, measured) in RESIDUAL_HEIGHT {
+        let simulated = interpolate(&heights, t);
+        println!("{t:6.2}  {simulated:6.2}  {measured:6.2}");
+        if (simulated - measured).abs() > HEIGHT_TOLERANCE {
+            failures.push(format!(
+                "height {simulated:.2} at T = {t}, measured {measured}"
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "deviates from the experiment: {failures:#?}"
+    );
+}
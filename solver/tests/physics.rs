@@ -0,0 +1,342 @@
+//! Physical validation against analytic solutions of simple flows.
+//!
+//! The position-based pressure and viscosity of the solver do not map directly to
+//! physical units, so these tests check the shape of the solutions with tolerances
+//! rather than absolute magnitudes. No-slip walls are modelled by layers of
+//! particles that are moved along a prescribed path after every solver step.
+
+use solver::{Parameters, Particle, Real, Shape, State, Vec2};
+
+/// Lattice spacing used by [`State::fill_shape`]
+const SPACING: Real = 0.09;
+
+/// Viscosity high enough for shear to cross a channel within a few seconds
+const CHANNEL_VISCOSITY: Real = 5000.0;
+
+fn rectangle(min: (Real, Real), max: (Real, Real)) -> Shape {
+    Shape::Rectangle {
+        min: Vec2::new(min.0, min.1),
+        max: Vec2::new(max.0, max.1),
+    }
+}
+
+/// Mean height and mean `value` of the particles in `bins` equal layers between
+/// the heights `min` and `max`, skipping empty layers.
+fn profile(
+    state: &State,
+    (min, max): (Real, Real),
+    bins: usize,
+    value: impl Fn(&Particle) -> Real,
+) -> Vec<(Real, Real)> {
+    let mut sums = vec![(0.0, 0.0, 0_usize); bins];
+    for p in &state.particles {
+        let t = (p.x.y - min) / (max - min);
+        if (0.0..1.0).contains(&t) {
+            let bin = &mut sums[(t * bins as Real) as usize];
+            bin.0 += p.x.y;
+            bin.1 += value(p);
+            bin.2 += 1;
+        }
+    }
+    sums.into_iter()
+        .filter(|&(_, _, n)| n > 0)
+        .map(|(y, v, n)| (y / n as Real, v / n as Real))
+        .collect()
+}
+
+/// Averages profiles taken at the same heights.
+fn average(profiles: &[Vec<(Real, Real)>]) -> Vec<(Real, Real)> {
+    let n = profiles.len() as Real;
+    (0..profiles[0].len())
+        .map(|i| {
+            let (y, v) = profiles
+                .iter()
+                .fold((0.0, 0.0), |(y, v), p| (y + p[i].0, v + p[i].1));
+            (y / n, v / n)
+        })
+        .collect()
+}
+
+/// Least-squares polynomial of the given degree, lowest coefficient first,
+/// together with the coefficient of determination.
+fn polyfit(points: &[(Real, Real)], degree: usize) -> (Vec<Real>, Real) {
+    let k = degree + 1;
+    // normal equations, solved by Gaussian elimination
+    let mut a = vec![vec![0.0; k + 1]; k];
+    for &(x, y) in points {
+        for (row, a_row) in a.iter_mut().enumerate() {
+            for (col, entry) in a_row[..k].iter_mut().enumerate() {
+                *entry += x.powi((row + col) as i32);
+            }
+            a_row[k] += y * x.powi(row as i32);
+        }
+    }
+    for i in 0..k {
+        let pivot = a[i].clone();
+        for row in &mut a[i + 1..] {
+            let f = row[i] / pivot[i];
+            for (entry, p) in row.iter_mut().zip(&pivot).skip(i) {
+                *entry -= f * p;
+            }
+        }
+    }
+    let mut coefficients = vec![0.0; k];
+    for i in (0..k).rev() {
+        let sum: Real = (i + 1..k).map(|c| a[i][c] * coefficients[c]).sum();
+        coefficients[i] = (a[i][k] - sum) / a[i][i];
+    }
+
+    let eval = |x: Real| coefficients.iter().rev().fold(0.0, |acc, &c| acc * x + c);
+    let mean = points.iter().map(|p| p.1).sum::<Real>() / points.len() as Real;
+    let residual: Real = points.iter().map(|&(x, y)| (y - eval(x)).powi(2)).sum();
+    let total: Real = points.iter().map(|&(_, y)| (y - mean).powi(2)).sum();
+    (coefficients, 1.0 - residual / total)
+}
+
+/// Water column at rest in a closed box.
+fn water_column() -> State {
+    let mut state = State::new();
+    state.set_domain(Vec2::new(2.0, 3.0));
+    state.fill_shape(&rectangle((0.05, 0.05), (1.95, 1.5)), Vec2::ZERO);
+    state
+}
+
+#[test]
+fn hydrostatic_pressure_grows_linearly_with_depth() {
+    let mut state = water_column();
+    for _ in 0..200 {
+        state.update();
+    }
+    let surface = state.particles.iter().map(|p| p.x.y).fold(0.0, Real::max);
+    let mut profiles = Vec::new();
+    for _ in 0..40 {
+        state.update();
+        // the layer at the floor lacks neighbors below it and is skipped
+        profiles.push(profile(
+            &state,
+            (2.0 * SPACING, surface),
+            8,
+            Particle::pressure,
+        ));
+    }
+    let profile = average(&profiles);
+
+    let (line, r2) = polyfit(&profile, 1);
+    assert!(line[1] < 0.0, "pressure increases with height: {profile:?}");
+    assert!(
+        r2 > 0.95,
+        "pressure is not linear in depth, R² = {r2}: {profile:?}"
+    );
+    for pair in profile.windows(2) {
+        assert!(
+            pair[1].1 < pair[0].1,
+            "pressure profile is not monotonic: {profile:?}"
+        );
+    }
+}
+
+#[test]
+fn still_water_column_settles() {
+    let mut state = water_column();
+    let initial = state.diagnostics();
+    let mut kinetic_energy = Vec::new();
+    for _ in 0..400 {
+        state.update();
+        kinetic_energy.push(state.diagnostics().kinetic_energy);
+    }
+    let early = kinetic_energy[..40].iter().sum::<Real>() / 40.0;
+    let late = kinetic_energy[360..].iter().sum::<Real>() / 40.0;
+    let final_state = state.diagnostics();
+
+    assert!(
+        late < 0.25 * early,
+        "kinetic energy does not decay: {early} early, {late} late"
+    );
+    assert!(
+        late < 5e-3 * initial.potential_energy,
+        "kinetic energy {late} remains large compared to the potential energy {}",
+        initial.potential_energy
+    );
+    // settling may compact the column, but must not lift it
+    assert!(final_state.potential_energy < 1.01 * initial.potential_energy);
+    let domain = state.domain();
+    assert!(state
+        .particles
+        .iter()
+        .all(|p| (0.0..=domain.x).contains(&p.x.x) && (0.0..=domain.y).contains(&p.x.y)));
+}
+
+#[test]
+fn rigid_rotation_has_twice_its_angular_velocity_as_vorticity() {
+    let center = Vec2::new(10.0, 7.0);
+    let angular_velocity = 1.5;
+    let mut state = State::new();
+    state.set_params(Parameters {
+        gravity: Vec2::ZERO,
+        ..Parameters::default()
+    });
+    state.fill_shape(
+        &Shape::Circle {
+            center,
+            radius: 2.0,
+        },
+        Vec2::ZERO,
+    );
+    // build the neighborhoods
+    state.step_substep();
+    for p in &mut state.particles {
+        let r = p.x - center;
+        p.set_velocity(angular_velocity * Vec2::new(-r.y, r.x));
+    }
+
+    let vorticity = state.vorticity();
+    // particles near the edge lack neighbors on one side
+    let interior: Vec<Real> = (state.particles.iter().zip(&vorticity))
+        .filter(|(p, _)| p.x.distance(center) < 1.5)
+        .map(|(_, w)| *w)
+        .collect();
+    let expected = 2.0 * angular_velocity;
+    for w in &interior {
+        assert!(
+            (w - expected).abs() < 0.05 * expected,
+            "vorticity {w} differs from {expected}"
+        );
+    }
+}
+
+/// Periodic channel whose fluid fills the heights in `gap`, bounded by wall layers
+/// below and above. Returns the indices of the bottom and top wall particles.
+fn channel(gap: (Real, Real), params: Parameters) -> (State, Vec<usize>, Vec<usize>) {
+    let width = 1.8;
+    let mut state = State::new();
+    state.set_domain(Vec2::new(width, gap.1 + 1.0));
+    state.set_periodic_x(true);
+    state.set_params(params);
+    // three wall layers on either side cover the smoothing radius
+    let fluid = rectangle(
+        (0.0, gap.0 - 3.0 * SPACING),
+        (width - 0.5 * SPACING, gap.1 + 2.5 * SPACING),
+    );
+    state.fill_shape(&fluid, Vec2::ZERO);
+    let layer = |above: bool| {
+        (state.particles.iter().enumerate())
+            .filter(|(_, p)| if above { p.x.y > gap.1 } else { p.x.y < gap.0 })
+            .map(|(i, _)| i)
+            .collect()
+    };
+    let (bottom, top) = (layer(false), layer(true));
+    (state, bottom, top)
+}
+
+/// Advances a [`channel`] by `frames` frames, holding the bottom wall still and
+/// sliding the top wall along x at `wall_speed`, and returns the time-averaged
+/// velocity profile across the gap over the last `average_frames` frames.
+fn run_channel(
+    mut state: State,
+    (bottom, top): (&[usize], &[usize]),
+    gap: (Real, Real),
+    wall_speed: Real,
+    (frames, average_frames): (usize, usize),
+) -> Vec<(Real, Real)> {
+    let initial: Vec<Vec2> = state.particles.iter().map(|p| p.x).collect();
+    let width = state.domain().x;
+    let substeps = state.params().substeps;
+    let mut profiles = Vec::new();
+    for frame in 0..frames {
+        for _ in 0..substeps {
+            state.step_substep();
+            let offset = wall_speed * state.time();
+            for (walls, speed, offset) in [(bottom, 0.0, 0.0), (top, wall_speed, offset)] {
+                for &i in walls {
+                    let p = &mut state.particles[i];
+                    p.x = initial[i] + Vec2::new(offset, 0.0);
+                    p.x.x = p.x.x.rem_euclid(width);
+                    p.set_velocity(Vec2::new(speed, 0.0));
+                }
+            }
+        }
+        if frame + average_frames >= frames {
+            profiles.push(profile(&state, gap, 8, |p| p.velocity().x));
+        }
+    }
+    average(&profiles)
+}
+
+#[test]
+fn couette_flow_has_linear_profile() {
+    let gap = (0.5, 1.5);
+    let wall_speed = 1.0;
+    let params = Parameters {
+        gravity: Vec2::ZERO,
+        linear_viscosity: CHANNEL_VISCOSITY,
+        quadratic_viscosity: 0.0,
+        ..Parameters::default()
+    };
+    let (state, bottom, top) = channel(gap, params);
+    let profile = run_channel(state, (&bottom, &top), gap, wall_speed, (300, 50));
+
+    let (line, r2) = polyfit(&profile, 1);
+    assert!(
+        r2 > 0.97,
+        "velocity is not linear across the gap, R² = {r2}: {profile:?}"
+    );
+    // the walls sit half a particle spacing outside the sampled gap
+    let expected = wall_speed / (gap.1 - gap.0 + SPACING);
+    assert!(
+        (line[1] - expected).abs() < 0.2 * expected,
+        "shear rate {} differs from {expected}: {profile:?}",
+        line[1]
+    );
+    for pair in profile.windows(2) {
+        assert!(
+            pair[1].1 > pair[0].1,
+            "velocity profile is not monotonic: {profile:?}"
+        );
+    }
+}
+
+#[test]
+fn poiseuille_flow_has_parabolic_profile() {
+    let gap = (0.5, 1.5);
+    let params = Parameters {
+        gravity: Vec2::new(1.0, 0.0),
+        linear_viscosity: CHANNEL_VISCOSITY,
+        quadratic_viscosity: 0.0,
+        ..Parameters::default()
+    };
+    let (state, bottom, top) = channel(gap, params);
+    let profile = run_channel(state, (&bottom, &top), gap, 0.0, (200, 100));
+
+    let (parabola, r2) = polyfit(&profile, 2);
+    assert!(
+        parabola[2] < 0.0,
+        "velocity profile is not concave: {profile:?}"
+    );
+    assert!(
+        r2 > 0.9,
+        "velocity is not parabolic across the gap, R² = {r2}: {profile:?}"
+    );
+    let vertex = -parabola[1] / (2.0 * parabola[2]);
+    let center = 0.5 * (gap.0 + gap.1);
+    assert!(
+        (vertex - center).abs() < 0.1 * (gap.1 - gap.0),
+        "fastest flow at {vertex} instead of the channel center {center}"
+    );
+    // compare the flatness with the analytic parabola vanishing at the walls,
+    // which sit half a particle spacing outside the sampled gap. Slip at the wall
+    // layers flattens the measured profile somewhat, while plug flow is far off.
+    let walls = (gap.0 - 0.5 * SPACING, gap.1 + 0.5 * SPACING);
+    let ratio = |values: &[Real]| {
+        let max = values.iter().copied().fold(Real::MIN, Real::max);
+        values.iter().sum::<Real>() / values.len() as Real / max
+    };
+    let measured: Vec<Real> = profile.iter().map(|p| p.1).collect();
+    let analytic: Vec<Real> = (profile.iter())
+        .map(|&(y, _)| (y - walls.0) * (walls.1 - y))
+        .collect();
+    let (measured, analytic) = (ratio(&measured), ratio(&analytic));
+    assert!(
+        (measured - analytic).abs() < 0.15,
+        "mean to maximum velocity ratio {measured} differs from {analytic}: {profile:?}"
+    );
+}
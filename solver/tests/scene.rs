@@ -0,0 +1,45 @@
+//! Scene loading and domain limits.
+
+use solver::{Real, Scene, State, Vec2, MAX_GRID_CELLS, MIN_DOMAIN_SIZE};
+
+/// Grid cell size, a third of the smallest domain
+const CELL_SIZE: Real = MIN_DOMAIN_SIZE / 3.0;
+
+fn grid_cells(domain: Vec2) -> Real {
+    let cells = (domain / CELL_SIZE).floor();
+    cells.x * cells.y
+}
+
+#[test]
+fn validates_scenes_built_in_code() {
+    let mut scene = Scene::default();
+    scene.domain.width = 1e9;
+    let mut state = State::new();
+    state.try_init_dam_break(10 * 10).unwrap();
+    assert!(state.load_scene(&scene).is_err());
+    assert_eq!(state.particles.len(), 100);
+}
+
+#[test]
+fn clamps_domains_to_the_grid_limit() {
+    let mut state = State::new();
+    for size in [
+        Vec2::splat(1e9),
+        Vec2::new(1e6, 1.0),
+        Vec2::new(1.0, Real::INFINITY),
+        Vec2::splat(Real::NAN),
+    ] {
+        state.set_domain(size);
+        let domain = state.domain();
+        assert!(
+            grid_cells(domain) <= MAX_GRID_CELLS as Real,
+            "{size} -> {domain}"
+        );
+        assert!(
+            domain.cmpge(Vec2::splat(MIN_DOMAIN_SIZE)).all(),
+            "{size} -> {domain}"
+        );
+    }
+    state.set_domain(Vec2::new(1e6, 1.0));
+    assert_eq!(state.domain().y, 1.0);
+}
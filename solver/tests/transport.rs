@@ -0,0 +1,34 @@
+//! Scalar channel settings of `State::set_scalars`.
+
+use solver::{ScalarChannel, SolverError, State};
+
+fn channels(names: &[&str]) -> Vec<ScalarChannel> {
+    names
+        .iter()
+        .map(|name| ScalarChannel {
+            name: (*name).to_owned(),
+            ..ScalarChannel::default()
+        })
+        .collect()
+}
+
+#[test]
+fn rejects_names_unusable_as_export_arrays() {
+    let mut state = State::new();
+    state.set_scalars(channels(&["dye", "salt_2"])).unwrap();
+    for name in ["", "2dye", "dye\" Name=\"x", "pressure", "vorticity"] {
+        assert_eq!(
+            state.set_scalars(channels(&[name])),
+            Err(SolverError::InvalidScalarName {
+                name: name.to_owned()
+            })
+        );
+    }
+    assert_eq!(
+        state.set_scalars(channels(&["dye", "dye"])),
+        Err(SolverError::DuplicateScalarName {
+            name: "dye".to_owned()
+        })
+    );
+    assert_eq!(state.scalars().len(), 2);
+}
@@ -0,0 +1,37 @@
+//! Flat copies of per-particle data in wasm memory, shared with the renderer and JS.
+
+use solver::{to_f32, State, MAX_PARTICLES};
+
+/// Particle data as tightly packed `f32` arrays, in particle order. Positions and
+/// velocities hold interleaved `x, y` pairs.
+///
+/// The buffers are allocated once for [`MAX_PARTICLES`] particles so that their
+/// addresses stay fixed, which lets JS keep views into them across frames.
+pub struct ParticleBuffers {
+    pub positions: Vec<f32>,
+    pub velocities: Vec<f32>,
+    pub pressures: Vec<f32>,
+}
+
+impl ParticleBuffers {
+    pub fn new() -> Self {
+        Self {
+            positions: Vec::with_capacity(2 * MAX_PARTICLES),
+            velocities: Vec::with_capacity(2 * MAX_PARTICLES),
+            pressures: Vec::with_capacity(MAX_PARTICLES),
+        }
+    }
+
+    /// Overwrites the buffers in place with the current particles of `state`.
+    #[allow(clippy::unnecessary_cast)] // `Real` casts are no-ops in one of the two precisions
+    pub fn update(&mut self, state: &State) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.pressures.clear();
+        for p in &state.particles {
+            self.positions.extend(to_f32(p.x).to_array());
+            self.velocities.extend(to_f32(p.velocity()).to_array());
+            self.pressures.push(p.pressure() as f32);
+        }
+    }
+}
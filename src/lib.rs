@@ -4,19 +4,58 @@
     clippy::cast_possible_truncation
 )]
 
+use solver::color::{dye_colors, Attribute, ColorMapping, Colormap, Range};
+use solver::{Interaction, InteractionKind, Real, Shape};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader};
+
+mod buffers;
+mod render;
+
+use buffers::ParticleBuffers;
+pub use render::RenderMode;
+use render::{Renderer, FLUID_COLOR};
 
 const DAM_PARTICLES: usize = 75 * 75;
 const BLOCK_PARTICLES: usize = 500;
-const MAX_PARTICLES: usize = solver::MAX_PARTICLES;
-const POINT_SIZE: f32 = 5.0;
+/// Radius of the eraser pointer mode in simulation units
+const ERASER_RADIUS: Real = 0.5;
 
 #[wasm_bindgen]
 pub struct Simulation {
-    context: WebGl2RenderingContext,
+    renderer: Renderer,
     state: solver::State,
+    scene: Option<solver::Scene>,
+    color_mapping: Option<ColorMapping>,
+    /// Value range mapped to colors in the last drawn frame
+    color_range: Option<(f32, f32)>,
+    /// Latest pointer position in simulation units while interacting
+    pointer: Option<solver::Vec2>,
+    /// Whether the pointer removes particles instead of applying an interaction
+    erasing: bool,
+    /// Particle data of the current frame, refreshed whenever the particles change
+    buffers: ParticleBuffers,
+}
+
+/// How the pointer acts on nearby particles while pressed.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerMode {
+    Attract,
+    Repel,
+    Drag,
+    /// Removes particles around the pointer
+    Erase,
+}
+
+impl PointerMode {
+    fn interaction_kind(self) -> Option<InteractionKind> {
+        match self {
+            Self::Attract => Some(InteractionKind::Attract),
+            Self::Repel => Some(InteractionKind::Repel),
+            Self::Drag => Some(InteractionKind::Drag),
+            Self::Erase => None,
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -28,10 +67,37 @@ impl Simulation {
         canvas: &web_sys::OffscreenCanvas,
         use_dark_colors: bool,
     ) -> Result<Simulation, JsValue> {
-        let context = init_webgl(canvas, use_dark_colors)?;
+        let renderer = Renderer::new(canvas, use_dark_colors)?;
         let mut state = solver::State::new();
         state.init_dam_break(DAM_PARTICLES);
-        Ok(Simulation { context, state })
+        state.set_safeguard(Some(solver::Safeguard::default()));
+        let mut sim = Simulation {
+            renderer,
+            state,
+            scene: None,
+            color_mapping: None,
+            color_range: None,
+            pointer: None,
+            erasing: false,
+            buffers: ParticleBuffers::new(),
+        };
+        sim.update_projection();
+        sim.update_buffers();
+        Ok(sim)
+    }
+
+    /// Replaces the simulation with the TOML scene description in `source`,
+    /// which is also used by subsequent resets.
+    ///
+    /// # Errors
+    /// Will return `Err` if the scene cannot be parsed or holds too many particles.
+    pub fn load_scene(&mut self, source: &str) -> Result<(), JsValue> {
+        let scene = solver::Scene::from_toml(source).map_err(|e| e.to_string())?;
+        self.state.load_scene(&scene).map_err(|e| e.to_string())?;
+        self.scene = Some(scene);
+        self.update_projection();
+        self.update_buffers();
+        Ok(())
     }
 
     #[must_use]
@@ -40,188 +106,591 @@ impl Simulation {
         self.state.particles.len()
     }
 
-    pub fn step(&mut self) {
-        self.state.update();
+    /// Advances the simulation by one frame. When the solver diverges, the
+    /// simulation is paused so that the broken state can be inspected or reset.
+    ///
+    /// # Errors
+    /// Will return `Err` if the parameters are invalid or the particles diverged.
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        match self.pointer {
+            // erasing works while paused too
+            Some(center) if self.erasing => {
+                self.remove_circle(center.x, center.y, ERASER_RADIUS);
+            }
+            Some(position) => self.state.move_interaction(position),
+            None => (),
+        }
+        let result = self.state.try_update();
+        self.update_buffers();
+        if let Err(e) = result {
+            self.state.set_paused(true);
+            return Err(e.to_string().into());
+        }
+        Ok(())
+    }
+
+    /// Simulated time in seconds since the last reset.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn time(&self) -> Real {
+        self.state.time()
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn paused(&self) -> bool {
+        self.state.paused()
+    }
+
+    /// Pausing keeps drawing the current frame on each [`Simulation::step`].
+    #[wasm_bindgen(setter)]
+    pub fn set_paused(&mut self, paused: bool) {
+        self.state.set_paused(paused);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn safeguard(&self) -> bool {
+        self.state.safeguard().is_some()
+    }
+
+    /// Enables rolling back and retrying frames that blow up, see [`solver::Safeguard`].
+    #[wasm_bindgen(setter)]
+    pub fn set_safeguard(&mut self, enabled: bool) {
+        self.state
+            .set_safeguard(enabled.then(solver::Safeguard::default));
+    }
+
+    /// Description of what the safeguard did during the last [`Simulation::step`], if anything.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn last_recovery(&self) -> Option<String> {
+        self.state.last_recovery().map(ToString::to_string)
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn time_scale(&self) -> Real {
+        self.state.time_scale()
+    }
+
+    /// Simulated time per displayed frame relative to real time, e.g. `0.25` for slow motion.
+    #[wasm_bindgen(setter)]
+    pub fn set_time_scale(&mut self, time_scale: Real) {
+        self.state.set_time_scale(time_scale);
+    }
+
+    /// Advances exactly one frame, also while paused.
+    pub fn step_frame(&mut self) {
+        self.state.step_frame();
+        self.update_buffers();
+    }
+
+    /// Advances a single solver step, also while paused.
+    pub fn step_substep(&mut self) {
+        self.state.step_substep();
+        self.update_buffers();
+    }
+
+    /// Horizontal gravity in m/s².
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn gravity_x(&self) -> Real {
+        self.parameter("gravity_x")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_gravity_x(&mut self, value: Real) {
+        self.set_parameter("gravity_x", value);
+    }
+
+    /// Vertical gravity in m/s², negative pointing down.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn gravity_y(&self) -> Real {
+        self.parameter("gravity_y")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_gravity_y(&mut self, value: Real) {
+        self.set_parameter("gravity_y", value);
+    }
+
+    /// Number of solver steps per frame.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn substeps(&self) -> usize {
+        self.state.params().substeps
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_substeps(&mut self, value: usize) {
+        self.set_parameter("substeps", value as Real);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn rest_density(&self) -> Real {
+        self.parameter("rest_density")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_rest_density(&mut self, value: Real) {
+        self.set_parameter("rest_density", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn stiffness(&self) -> Real {
+        self.parameter("stiffness")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_stiffness(&mut self, value: Real) {
+        self.set_parameter("stiffness", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn stiffness_near(&self) -> Real {
+        self.parameter("stiffness_near")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_stiffness_near(&mut self, value: Real) {
+        self.set_parameter("stiffness_near", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn surface_tension(&self) -> Real {
+        self.parameter("surface_tension")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_surface_tension(&mut self, value: Real) {
+        self.set_parameter("surface_tension", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn linear_viscosity(&self) -> Real {
+        self.parameter("linear_viscosity")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_linear_viscosity(&mut self, value: Real) {
+        self.set_parameter("linear_viscosity", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn quadratic_viscosity(&self) -> Real {
+        self.parameter("quadratic_viscosity")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_quadratic_viscosity(&mut self, value: Real) {
+        self.set_parameter("quadratic_viscosity", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn vorticity_confinement(&self) -> Real {
+        self.parameter("vorticity_confinement")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_vorticity_confinement(&mut self, value: Real) {
+        self.set_parameter("vorticity_confinement", value);
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn xsph(&self) -> Real {
+        self.parameter("xsph")
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_xsph(&mut self, value: Real) {
+        self.set_parameter("xsph", value);
+    }
+
+    /// Width of the view in simulation units, for converting pointer positions.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn view_width(&self) -> f32 {
+        self.state.view_size().0
+    }
+
+    /// Height of the view in simulation units, for converting pointer positions.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn view_height(&self) -> f32 {
+        self.state.view_size().1
     }
 
-    pub fn add_block(&mut self) {
-        if self.state.particles.len() < MAX_PARTICLES - BLOCK_PARTICLES {
-            self.state.init_block(BLOCK_PARTICLES);
+    /// Starts acting on the particles around `(x, y)` in simulation units.
+    pub fn pointer_down(&mut self, x: f32, y: f32, mode: PointerMode) {
+        let position = solver::Vec2::new(Real::from(x), Real::from(y));
+        let kind = mode.interaction_kind();
+        self.state
+            .set_interaction(kind.map(|kind| Interaction::new(kind, position)));
+        self.erasing = kind.is_none();
+        self.pointer = Some(position);
+    }
+
+    /// Moves the active interaction to `(x, y)` in simulation units, taking effect
+    /// with the next step.
+    pub fn pointer_move(&mut self, x: f32, y: f32) {
+        if self.pointer.is_some() {
+            self.pointer = Some(solver::Vec2::new(Real::from(x), Real::from(y)));
         }
     }
 
-    pub fn reset(&mut self) {
-        self.state.clear();
-        self.state.init_dam_break(DAM_PARTICLES);
+    pub fn pointer_up(&mut self) {
+        self.state.set_interaction(None);
+        self.pointer = None;
     }
 
-    pub fn draw(&self) {
-        let vertices: Vec<f32> = self
+    /// Removes the particles within `radius` of `(x, y)` in simulation units and
+    /// returns their number.
+    pub fn remove_circle(&mut self, x: Real, y: Real, radius: Real) -> usize {
+        self.remove_in(&Shape::Circle {
+            center: solver::Vec2::new(x, y),
+            radius,
+        })
+    }
+
+    /// Removes the particles inside the rectangle from `(min_x, min_y)` to
+    /// `(max_x, max_y)` in simulation units and returns their number.
+    pub fn remove_rectangle(
+        &mut self,
+        min_x: Real,
+        min_y: Real,
+        max_x: Real,
+        max_y: Real,
+    ) -> usize {
+        self.remove_in(&Shape::Rectangle {
+            min: solver::Vec2::new(min_x, min_y),
+            max: solver::Vec2::new(max_x, max_y),
+        })
+    }
+
+    /// Removes the particles inside the polygon with interleaved `x, y` vertices in
+    /// simulation units and returns their number.
+    pub fn remove_polygon(&mut self, vertices: &[Real]) -> usize {
+        self.remove_in(&Shape::Polygon {
+            vertices: vertices
+                .chunks_exact(2)
+                .map(|v| solver::Vec2::new(v[0], v[1]))
+                .collect(),
+        })
+    }
+
+    /// Removes the particles at `indices` into the particle buffers and returns
+    /// their number. Indices out of range are ignored.
+    pub fn remove_particles(&mut self, indices: &[u32]) -> usize {
+        let removed = self
             .state
-            .particles
-            .iter()
-            .flat_map(|p| p.x.to_array())
-            .collect();
-        unsafe {
-            // Note that `Float32Array::view` is somewhat dangerous (hence the
-            // `unsafe`!). This is creating a raw view into our module's
-            // `WebAssembly.Memory` buffer, but if we allocate more pages for ourself
-            // (aka do a memory allocation in Rust) it'll cause the buffer to change,
-            // causing the `Float32Array` to be invalid.
-            //
-            // As a result, after `Float32Array::view` we have to be very careful not to
-            // do any memory allocations before it's dropped.
-            let positions_array_buf_view = js_sys::Float32Array::view(&vertices);
-
-            self.context.buffer_sub_data_with_i32_and_array_buffer_view(
-                WebGl2RenderingContext::ARRAY_BUFFER,
-                0,
-                &positions_array_buf_view,
-            );
-        }
+            .remove_particles(indices.iter().map(|&i| i as usize));
+        self.update_buffers();
+        removed
+    }
 
-        let vert_count = (vertices.len() / 2) as i32;
-        self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
-        self.context
-            .draw_arrays(WebGl2RenderingContext::POINTS, 0, vert_count);
+    /// # Errors
+    /// Will return `Err` if the block does not fit within the particle capacity.
+    pub fn add_block(&mut self) -> Result<(), JsValue> {
+        self.state
+            .try_init_block(BLOCK_PARTICLES)
+            .map_err(|e| e.to_string())?;
+        self.update_buffers();
+        Ok(())
     }
-}
 
-fn init_webgl(
-    canvas: &web_sys::OffscreenCanvas,
-    use_dark_colors: bool,
-) -> Result<WebGl2RenderingContext, JsValue> {
-    // set up canvas and webgl context handle
-    canvas.set_width(solver::WINDOW_WIDTH);
-    canvas.set_height(solver::WINDOW_HEIGHT);
-    let context = canvas
-        .get_context("webgl2")?
-        .unwrap()
-        .dyn_into::<WebGl2RenderingContext>()?;
-
-    if use_dark_colors {
-        context.clear_color(0.1, 0.1, 0.1, 1.0);
-    } else {
-        context.clear_color(0.9, 0.9, 0.9, 1.0);
-    }
-
-    let vert_shader = compile_shader(
-        &context,
-        WebGl2RenderingContext::VERTEX_SHADER,
-        format!(
-            r##"#version 300 es
-        uniform mat4 u_matrix;
-        in vec2 in_position;
-        void main() {{
-            gl_PointSize = {:.1};
-            gl_Position = u_matrix * vec4(in_position, 0.0, 1.0);
-        }}
-        "##,
-            POINT_SIZE
-        )
-        .as_str(),
-    )?;
-
-    let frag_shader = compile_shader(
-        &context,
-        WebGl2RenderingContext::FRAGMENT_SHADER,
-        r#"#version 300 es
-        precision mediump float;
-        out vec4 out_color;
-        void main() {
-            out_color = vec4(0.2, 0.6, 1.0, 1.0);
+    pub fn reset(&mut self) {
+        match &self.scene {
+            Some(scene) => {
+                // the scene was loaded successfully before, so it fits again
+                let _ = self.state.load_scene(scene);
+            }
+            None => {
+                self.state.clear();
+                self.state.init_dam_break(DAM_PARTICLES);
+            }
         }
-        "#,
-    )?;
-    let program = link_program(&context, &vert_shader, &frag_shader)?;
-    context.use_program(Some(&program));
-
-    // uniforms
-    let uniform_location = context
-        .get_uniform_location(&program, "u_matrix")
-        .expect("Unable to get shader projection matrix uniform location");
-    let ortho_matrix = cgmath::ortho(0.0, solver::VIEW_WIDTH, 0.0, solver::VIEW_HEIGHT, 0.0, 1.0);
-    let ortho_matrix_flattened_ref: &[f32; 16] = ortho_matrix.as_ref();
-    context.uniform_matrix4fv_with_f32_array(
-        Some(&uniform_location),
-        false,
-        ortho_matrix_flattened_ref,
-    );
-
-    // attributes
-    let position_attribute_location = context.get_attrib_location(&program, "in_position") as u32;
-    let buffer = context.create_buffer().ok_or("Failed to create buffer")?;
-    context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
-    context.vertex_attrib_pointer_with_i32(
-        position_attribute_location,
-        2,
-        WebGl2RenderingContext::FLOAT,
-        false,
-        0,
-        0,
-    );
-    context.enable_vertex_attrib_array(position_attribute_location);
-
-    // allocate vertex buffer initial state
-    let zeroed = vec![0.0; MAX_PARTICLES * 2];
-    unsafe {
-        let positions_array_buf_view = js_sys::Float32Array::view(&zeroed);
-
-        context.buffer_data_with_array_buffer_view(
-            WebGl2RenderingContext::ARRAY_BUFFER,
-            &positions_array_buf_view,
-            WebGl2RenderingContext::DYNAMIC_DRAW,
+        self.update_buffers();
+    }
+
+    /// [`solver::Diagnostics`] of the current frame as an object with the same,
+    /// snake_case, field names. The neighbor histogram is a `Uint32Array`.
+    #[must_use]
+    pub fn diagnostics(&self) -> JsValue {
+        let d = self.state.diagnostics();
+        let histogram: Vec<u32> = d.neighbor_histogram.iter().map(|&n| n as u32).collect();
+        js_object([
+            ("time", d.time.into()),
+            ("particles", d.particles.into()),
+            ("kinetic_energy", d.kinetic_energy.into()),
+            ("potential_energy", d.potential_energy.into()),
+            ("max_speed", d.max_speed.into()),
+            ("mean_speed", d.mean_speed.into()),
+            ("mean_density_error", d.mean_density_error.into()),
+            ("max_density_error", d.max_density_error.into()),
+            (
+                "neighbor_histogram",
+                js_sys::Uint32Array::from(histogram.as_slice()).into(),
+            ),
+            ("truncated_neighbors", d.truncated_neighbors.into()),
+            ("boundary_particles", d.boundary_particles.into()),
+        ])
+    }
+
+    /// Indices of the particles within `radius` of `(x, y)` in simulation units.
+    #[must_use]
+    pub fn particles_within(&self, x: Real, y: Real, radius: Real) -> Vec<u32> {
+        to_u32(&self.state.particles_within(solver::Vec2::new(x, y), radius))
+    }
+
+    /// Indices of the particles inside the rectangle from `(min_x, min_y)` to
+    /// `(max_x, max_y)` in simulation units.
+    #[must_use]
+    pub fn particles_in_rect(
+        &self,
+        min_x: Real,
+        min_y: Real,
+        max_x: Real,
+        max_y: Real,
+    ) -> Vec<u32> {
+        let (min, max) = (
+            solver::Vec2::new(min_x, min_y),
+            solver::Vec2::new(max_x, max_y),
         );
+        to_u32(&self.state.particles_in_rect(min, max))
+    }
+
+    /// Indices of the `k` particles closest to `(x, y)` in simulation units, nearest first.
+    #[must_use]
+    pub fn k_nearest(&self, x: Real, y: Real, k: usize) -> Vec<u32> {
+        to_u32(&self.state.k_nearest(solver::Vec2::new(x, y), k))
+    }
+
+    /// [`solver::FieldSample`] at `(x, y)` in simulation units as an object with
+    /// `density`, `velocity_x`, `velocity_y`, `pressure` and `neighbors`.
+    #[must_use]
+    pub fn sample(&self, x: Real, y: Real) -> JsValue {
+        let sample = self.state.sample(solver::Vec2::new(x, y));
+        js_object([
+            ("density", sample.density.into()),
+            ("velocity_x", sample.velocity.x.into()),
+            ("velocity_y", sample.velocity.y.into()),
+            ("pressure", sample.pressure.into()),
+            ("neighbors", sample.neighbors.into()),
+        ])
+    }
+
+    /// Interleaved `x, y` positions of all particles in simulation units.
+    ///
+    /// This and the other particle views alias wasm memory instead of copying it.
+    /// Their contents are overwritten in place by every call that advances or
+    /// modifies the simulation, and their length only covers the particles that
+    /// existed when the view was created, so request a new view after the particle
+    /// count changes. Views must not be used after the simulation is freed.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn positions(&self) -> js_sys::Float32Array {
+        // SAFETY: the buffers never reallocate and live as long as `self`, see above
+        unsafe { js_sys::Float32Array::view(&self.buffers.positions) }
+    }
+
+    /// Interleaved `x, y` velocities of all particles, with the same lifetime rules
+    /// as [`Simulation::positions`].
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn velocities(&self) -> js_sys::Float32Array {
+        // SAFETY: see `positions`
+        unsafe { js_sys::Float32Array::view(&self.buffers.velocities) }
+    }
+
+    /// Pressure of all particles, with the same lifetime rules as [`Simulation::positions`].
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn pressures(&self) -> js_sys::Float32Array {
+        // SAFETY: see `positions`
+        unsafe { js_sys::Float32Array::view(&self.buffers.pressures) }
+    }
+
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn render_mode(&self) -> RenderMode {
+        self.renderer.mode()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.renderer.set_mode(mode);
+    }
+
+    /// Colors particles by the named quantity, see [`color_attributes`], or
+    /// disables coloring for `"none"`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the quantity is unknown.
+    pub fn set_color_by(&mut self, attribute: &str) -> Result<(), JsValue> {
+        if attribute == "none" {
+            self.color_mapping = None;
+            return Ok(());
+        }
+        let attribute = Attribute::from_name(attribute)
+            .ok_or_else(|| format!("Unknown quantity {attribute}"))?;
+        self.color_mapping = Some(match self.color_mapping {
+            Some(mapping) => ColorMapping {
+                attribute,
+                ..mapping
+            },
+            None => ColorMapping::new(attribute),
+        });
+        Ok(())
+    }
+
+    /// Selects the named colormap, see [`colormaps`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the colormap is unknown.
+    pub fn set_colormap(&mut self, name: &str) -> Result<(), JsValue> {
+        let colormap =
+            Colormap::from_name(name).ok_or_else(|| format!("Unknown colormap {name}"))?;
+        if let Some(mapping) = self.color_mapping.as_mut() {
+            mapping.colormap = colormap;
+        }
+        Ok(())
+    }
+
+    /// Maps the values from `min` to `max` onto the colormap.
+    pub fn set_color_range(&mut self, min: f32, max: f32) {
+        if let Some(mapping) = self.color_mapping.as_mut() {
+            mapping.range = Range::Fixed(min, max);
+        }
+    }
+
+    /// Fits the colormap to the values of each frame.
+    pub fn set_auto_color_range(&mut self) {
+        if let Some(mapping) = self.color_mapping.as_mut() {
+            mapping.range = Range::Auto;
+        }
+    }
+
+    /// `[min, max]` of the values mapped to colors in the last drawn frame, empty
+    /// while coloring is disabled or particles are drawn as metaballs.
+    #[must_use]
+    #[wasm_bindgen(getter)]
+    pub fn color_range(&self) -> Vec<f32> {
+        self.color_range
+            .map(|(min, max)| vec![min, max])
+            .unwrap_or_default()
+    }
+
+    pub fn draw(&mut self) {
+        // metaballs are shaded uniformly, so colors are only needed for points
+        let colors: Vec<f32> = match (self.renderer.mode(), self.color_mapping) {
+            (RenderMode::Metaballs, _) => {
+                self.color_range = None;
+                Vec::new()
+            }
+            (RenderMode::Points, Some(mapping)) => {
+                let (colors, range) = mapping.colors(&self.state);
+                self.color_range = Some(range);
+                colors.into_iter().flatten().collect()
+            }
+            (RenderMode::Points, None) => {
+                self.color_range = None;
+                dye_colors(&self.state, FLUID_COLOR)
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            }
+        };
+        self.renderer.draw(&self.buffers.positions, &colors);
     }
-    Ok(context)
 }
 
-fn compile_shader(
-    context: &WebGl2RenderingContext,
-    shader_type: u32,
-    source: &str,
-) -> Result<WebGlShader, String> {
-    let shader = context
-        .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-    context.shader_source(&shader, source);
-    context.compile_shader(&shader);
-
-    if context
-        .get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS)
-        .as_bool()
-        .unwrap_or(false)
-    {
-        Ok(shader)
-    } else {
-        Err(context
-            .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+/// Tunable solver parameters as `{ name, min, max, step, default }` objects, where
+/// `name` is also the name of the matching [`Simulation`] property.
+#[must_use]
+#[wasm_bindgen]
+pub fn parameters() -> Vec<JsValue> {
+    solver::Parameters::INFO
+        .iter()
+        .map(|info| {
+            js_object([
+                ("name", JsValue::from_str(info.name)),
+                ("min", JsValue::from(info.min)),
+                ("max", JsValue::from(info.max)),
+                ("step", JsValue::from(info.step)),
+                ("default", JsValue::from(info.default)),
+            ])
+        })
+        .collect()
+}
+
+/// Converts particle indices for a `Uint32Array`.
+fn to_u32(indices: &[usize]) -> Vec<u32> {
+    indices.iter().map(|&i| i as u32).collect()
+}
+
+/// Builds a plain JS object from property names and values.
+fn js_object<const N: usize>(entries: [(&str, JsValue); N]) -> JsValue {
+    let object = js_sys::Object::new();
+    for (key, value) in entries {
+        // setting a property on a plain object cannot fail
+        let _ = js_sys::Reflect::set(&object, &JsValue::from_str(key), &value);
     }
+    object.into()
+}
+
+/// Names of the quantities particles can be colored by.
+#[must_use]
+#[wasm_bindgen]
+pub fn color_attributes() -> Vec<JsValue> {
+    Attribute::ALL
+        .iter()
+        .map(|a| JsValue::from_str(a.name()))
+        .collect()
 }
 
-fn link_program(
-    context: &WebGl2RenderingContext,
-    vert_shader: &WebGlShader,
-    frag_shader: &WebGlShader,
-) -> Result<WebGlProgram, String> {
-    let program = context
-        .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-
-    context.attach_shader(&program, vert_shader);
-    context.attach_shader(&program, frag_shader);
-    context.link_program(&program);
-
-    if context
-        .get_program_parameter(&program, WebGl2RenderingContext::LINK_STATUS)
-        .as_bool()
-        .unwrap_or(false)
-    {
-        Ok(program)
-    } else {
-        Err(context
-            .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+/// Names of the available colormaps.
+#[must_use]
+#[wasm_bindgen]
+pub fn colormaps() -> Vec<JsValue> {
+    Colormap::ALL
+        .iter()
+        .map(|c| JsValue::from_str(c.name()))
+        .collect()
+}
+
+impl Simulation {
+    fn update_projection(&self) {
+        self.renderer.set_view(self.state.view_size());
+    }
+
+    fn parameter(&self, name: &str) -> Real {
+        self.state.params().get(name).unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Real) {
+        let mut params = *self.state.params();
+        params.set(name, value);
+        // invalid input keeps the previous parameters
+        let _ = self.state.try_set_params(params);
+    }
+
+    fn remove_in(&mut self, shape: &Shape) -> usize {
+        let removed = self.state.remove_particles_in(shape);
+        self.update_buffers();
+        removed
+    }
+
+    fn update_buffers(&mut self) {
+        self.buffers.update(&self.state);
     }
 }
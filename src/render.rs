@@ -0,0 +1,441 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    WebGl2RenderingContext as Gl, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlShader,
+    WebGlTexture, WebGlUniformLocation,
+};
+
+const MAX_PARTICLES: usize = solver::MAX_PARTICLES;
+const POINT_SIZE: f32 = 5.0;
+const POSITION_LOCATION: u32 = 0;
+const COLOR_LOCATION: u32 = 1;
+pub const FLUID_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+/// Radius of a particle's density splat in simulation units
+const BLOB_RADIUS: f32 = 0.15;
+/// Splatted density above which a pixel is considered fluid
+const BLOB_THRESHOLD: f32 = 0.6;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Every particle as a flat point in its own color
+    Points,
+    /// Screen-space metaballs, a thresholded and shaded density field
+    Metaballs,
+}
+
+struct Program {
+    program: WebGlProgram,
+    matrix: Option<WebGlUniformLocation>,
+}
+
+pub struct Renderer {
+    context: Gl,
+    mode: RenderMode,
+    background: [f32; 3],
+    position_buffer: WebGlBuffer,
+    color_buffer: WebGlBuffer,
+    points: Program,
+    splat: Program,
+    splat_size_location: WebGlUniformLocation,
+    shade: Program,
+    framebuffer: WebGlFramebuffer,
+    density_texture: WebGlTexture,
+}
+
+impl Renderer {
+    /// # Errors
+    /// Will return `Err` if unable to initialize webgl2 context and compile/link shader programs.
+    pub fn new(canvas: &web_sys::OffscreenCanvas, use_dark_colors: bool) -> Result<Self, JsValue> {
+        // set up canvas and webgl context handle
+        canvas.set_width(solver::WINDOW_WIDTH);
+        canvas.set_height(solver::WINDOW_HEIGHT);
+        let context = canvas.get_context("webgl2")?.unwrap().dyn_into::<Gl>()?;
+
+        let background = if use_dark_colors {
+            [0.1, 0.1, 0.1]
+        } else {
+            [0.9, 0.9, 0.9]
+        };
+
+        let points = Program::new(
+            &context,
+            &format!(
+                r##"#version 300 es
+            uniform mat4 u_matrix;
+            in vec2 in_position;
+            in vec3 in_color;
+            out vec3 v_color;
+            void main() {{
+                v_color = in_color;
+                gl_PointSize = {POINT_SIZE:.1};
+                gl_Position = u_matrix * vec4(in_position, 0.0, 1.0);
+            }}
+            "##
+            ),
+            r#"#version 300 es
+            precision mediump float;
+            in vec3 v_color;
+            out vec4 out_color;
+            void main() {
+                out_color = vec4(v_color, 1.0);
+            }
+            "#,
+        )?;
+
+        // metaballs: additive splats of a smooth kernel into an offscreen density texture
+        let splat = Program::new(
+            &context,
+            r#"#version 300 es
+            uniform mat4 u_matrix;
+            uniform float u_point_size;
+            in vec2 in_position;
+            void main() {
+                gl_PointSize = u_point_size;
+                gl_Position = u_matrix * vec4(in_position, 0.0, 1.0);
+            }
+            "#,
+            r#"#version 300 es
+            precision mediump float;
+            uniform float u_strength;
+            out vec4 out_color;
+            void main() {
+                vec2 c = 2.0 * gl_PointCoord - 1.0;
+                float r2 = dot(c, c);
+                if (r2 > 1.0) {
+                    discard;
+                }
+                float w = 1.0 - r2;
+                out_color = vec4(u_strength * w * w * w);
+            }
+            "#,
+        )?;
+        let splat_size_location = splat.uniform(&context, "u_point_size")?;
+
+        // metaballs: threshold and shade the density texture on a fullscreen triangle
+        let shade = Program::new(
+            &context,
+            r#"#version 300 es
+            out vec2 v_uv;
+            void main() {
+                vec2 p = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+                v_uv = p;
+                gl_Position = vec4(2.0 * p - 1.0, 0.0, 1.0);
+            }
+            "#,
+            r#"#version 300 es
+            precision mediump float;
+            uniform sampler2D u_density;
+            uniform float u_scale;
+            uniform float u_threshold;
+            uniform vec3 u_color;
+            uniform vec3 u_background;
+            in vec2 v_uv;
+            out vec4 out_color;
+
+            float density(vec2 uv) {
+                return texture(u_density, uv).r * u_scale;
+            }
+
+            void main() {
+                vec2 texel = 1.0 / vec2(textureSize(u_density, 0));
+                float d = density(v_uv);
+                float coverage = smoothstep(u_threshold, u_threshold + fwidth(d), d);
+                if (coverage <= 0.0) {
+                    out_color = vec4(u_background, 1.0);
+                    return;
+                }
+                // the density gradient acts as a surface normal of a fluid layer
+                vec2 gradient = vec2(
+                    density(v_uv + vec2(texel.x, 0.0)) - density(v_uv - vec2(texel.x, 0.0)),
+                    density(v_uv + vec2(0.0, texel.y)) - density(v_uv - vec2(0.0, texel.y)));
+                vec3 normal = normalize(vec3(-gradient, 0.5));
+
+                // light passing through a layer whose thickness follows the density,
+                // seen through a surface that bends it along the normal
+                float thickness = max(density(v_uv + 8.0 * texel * normal.xy) - u_threshold, 0.0);
+                vec3 transmitted = mix(u_background, u_color, 0.75);
+                vec3 deep = 0.45 * u_color;
+                vec3 color = mix(deep, transmitted, exp(-0.3 * thickness));
+                float diffuse = max(dot(normal, normalize(vec3(-0.4, 0.6, 1.0))), 0.0);
+                color *= 0.8 + 0.25 * diffuse;
+
+                // highlight the band just inside the surface
+                float edge = 1.0 - smoothstep(u_threshold, 1.6 * u_threshold, d);
+                color = mix(color, vec3(1.0), 0.55 * edge);
+                out_color = vec4(mix(u_background, color, coverage), 1.0);
+            }
+            "#,
+        )?;
+
+        // half-float targets keep the summed density unclamped where supported
+        let half_float = context.get_extension("EXT_color_buffer_float")?.is_some();
+        // 8-bit targets saturate at one, so their splats are scaled down
+        let splat_strength = if half_float { 1.0 } else { 0.125 };
+        let (framebuffer, density_texture) = create_density_target(&context, half_float)?;
+
+        context.use_program(Some(&splat.program));
+        context.uniform1f(
+            Some(&splat.uniform(&context, "u_strength")?),
+            splat_strength,
+        );
+        context.use_program(Some(&shade.program));
+        context.uniform1i(Some(&shade.uniform(&context, "u_density")?), 0);
+        context.uniform1f(
+            Some(&shade.uniform(&context, "u_scale")?),
+            1.0 / splat_strength,
+        );
+        context.uniform1f(
+            Some(&shade.uniform(&context, "u_threshold")?),
+            BLOB_THRESHOLD,
+        );
+        let [r, g, b] = FLUID_COLOR;
+        context.uniform3f(Some(&shade.uniform(&context, "u_color")?), r, g, b);
+        let [r, g, b] = background;
+        context.uniform3f(Some(&shade.uniform(&context, "u_background")?), r, g, b);
+
+        // attributes, shared by all programs through fixed locations
+        let position_buffer = create_vertex_buffer(&context, POSITION_LOCATION, 2)?;
+        let color_buffer = create_vertex_buffer(&context, COLOR_LOCATION, 3)?;
+
+        Ok(Self {
+            context,
+            mode: RenderMode::Points,
+            background,
+            position_buffer,
+            color_buffer,
+            points,
+            splat,
+            splat_size_location,
+            shade,
+            framebuffer,
+            density_texture,
+        })
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: RenderMode) {
+        self.mode = mode;
+    }
+
+    /// Shows `(0, 0)` to `view_size` in simulation units.
+    pub fn set_view(&self, (width, height): (f32, f32)) {
+        let ortho_matrix = cgmath::ortho(0.0, width, 0.0, height, 0.0, 1.0);
+        let ortho_matrix_flattened_ref: &[f32; 16] = ortho_matrix.as_ref();
+        for program in [&self.points, &self.splat] {
+            self.context.use_program(Some(&program.program));
+            self.context.uniform_matrix4fv_with_f32_array(
+                program.matrix.as_ref(),
+                false,
+                ortho_matrix_flattened_ref,
+            );
+        }
+        let pixels_per_unit = solver::WINDOW_WIDTH as f32 / width;
+        self.context.use_program(Some(&self.splat.program));
+        self.context.uniform1f(
+            Some(&self.splat_size_location),
+            2.0 * BLOB_RADIUS * pixels_per_unit,
+        );
+    }
+
+    /// Draws the particles at `vertices`, given as consecutive x and y coordinates,
+    /// with consecutive RGB `colors`. Colors are only used by [`RenderMode::Points`].
+    pub fn draw(&self, vertices: &[f32], colors: &[f32]) {
+        self.upload(&self.position_buffer, vertices);
+        if self.mode == RenderMode::Points {
+            self.upload(&self.color_buffer, colors);
+        }
+
+        let vert_count = (vertices.len() / 2) as i32;
+        let gl = &self.context;
+        match self.mode {
+            RenderMode::Points => {
+                let [r, g, b] = self.background;
+                gl.clear_color(r, g, b, 1.0);
+                gl.clear(Gl::COLOR_BUFFER_BIT);
+                gl.use_program(Some(&self.points.program));
+                gl.draw_arrays(Gl::POINTS, 0, vert_count);
+            }
+            RenderMode::Metaballs => {
+                gl.bind_framebuffer(Gl::FRAMEBUFFER, Some(&self.framebuffer));
+                gl.clear_color(0.0, 0.0, 0.0, 0.0);
+                gl.clear(Gl::COLOR_BUFFER_BIT);
+                gl.enable(Gl::BLEND);
+                gl.blend_func(Gl::ONE, Gl::ONE);
+                gl.use_program(Some(&self.splat.program));
+                gl.draw_arrays(Gl::POINTS, 0, vert_count);
+                gl.disable(Gl::BLEND);
+
+                gl.bind_framebuffer(Gl::FRAMEBUFFER, None);
+                gl.active_texture(Gl::TEXTURE0);
+                gl.bind_texture(Gl::TEXTURE_2D, Some(&self.density_texture));
+                gl.use_program(Some(&self.shade.program));
+                gl.draw_arrays(Gl::TRIANGLES, 0, 3);
+            }
+        }
+    }
+}
+
+impl Renderer {
+    fn upload(&self, buffer: &WebGlBuffer, data: &[f32]) {
+        self.context.bind_buffer(Gl::ARRAY_BUFFER, Some(buffer));
+        unsafe {
+            // Note that `Float32Array::view` is somewhat dangerous (hence the
+            // `unsafe`!). This is creating a raw view into our module's
+            // `WebAssembly.Memory` buffer, but if we allocate more pages for ourself
+            // (aka do a memory allocation in Rust) it'll cause the buffer to change,
+            // causing the `Float32Array` to be invalid.
+            //
+            // As a result, after `Float32Array::view` we have to be very careful not to
+            // do any memory allocations before it's dropped.
+            let array_buf_view = js_sys::Float32Array::view(data);
+
+            self.context.buffer_sub_data_with_i32_and_array_buffer_view(
+                Gl::ARRAY_BUFFER,
+                0,
+                &array_buf_view,
+            );
+        }
+    }
+}
+
+impl Program {
+    fn new(context: &Gl, vert_source: &str, frag_source: &str) -> Result<Self, String> {
+        let vert_shader = compile_shader(context, Gl::VERTEX_SHADER, vert_source)?;
+        let frag_shader = compile_shader(context, Gl::FRAGMENT_SHADER, frag_source)?;
+        let program = link_program(context, &vert_shader, &frag_shader)?;
+        let matrix = context.get_uniform_location(&program, "u_matrix");
+        Ok(Self { program, matrix })
+    }
+
+    fn uniform(&self, context: &Gl, name: &str) -> Result<WebGlUniformLocation, String> {
+        context
+            .get_uniform_location(&self.program, name)
+            .ok_or_else(|| format!("Unable to get shader uniform location {name}"))
+    }
+}
+
+/// Buffer with room for `size` floats per particle, bound to the attribute at `location`.
+fn create_vertex_buffer(context: &Gl, location: u32, size: i32) -> Result<WebGlBuffer, JsValue> {
+    let buffer = context.create_buffer().ok_or("Failed to create buffer")?;
+    context.bind_buffer(Gl::ARRAY_BUFFER, Some(&buffer));
+    context.vertex_attrib_pointer_with_i32(location, size, Gl::FLOAT, false, 0, 0);
+    context.enable_vertex_attrib_array(location);
+
+    // allocate vertex buffer initial state
+    let zeroed = vec![0.0; MAX_PARTICLES * size as usize];
+    unsafe {
+        let array_buf_view = js_sys::Float32Array::view(&zeroed);
+
+        context.buffer_data_with_array_buffer_view(
+            Gl::ARRAY_BUFFER,
+            &array_buf_view,
+            Gl::DYNAMIC_DRAW,
+        );
+    }
+    Ok(buffer)
+}
+
+/// Framebuffer rendering into a canvas-sized texture that accumulates splatted density.
+fn create_density_target(
+    context: &Gl,
+    half_float: bool,
+) -> Result<(WebGlFramebuffer, WebGlTexture), JsValue> {
+    let texture = context.create_texture().ok_or("Failed to create texture")?;
+    context.bind_texture(Gl::TEXTURE_2D, Some(&texture));
+    let (internal_format, data_type) = if half_float {
+        (Gl::RGBA16F, Gl::HALF_FLOAT)
+    } else {
+        (Gl::RGBA8, Gl::UNSIGNED_BYTE)
+    };
+    context.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        Gl::TEXTURE_2D,
+        0,
+        internal_format as i32,
+        solver::WINDOW_WIDTH as i32,
+        solver::WINDOW_HEIGHT as i32,
+        0,
+        Gl::RGBA,
+        data_type,
+        None,
+    )?;
+    for (parameter, value) in [
+        (Gl::TEXTURE_MIN_FILTER, Gl::LINEAR),
+        (Gl::TEXTURE_MAG_FILTER, Gl::LINEAR),
+        (Gl::TEXTURE_WRAP_S, Gl::CLAMP_TO_EDGE),
+        (Gl::TEXTURE_WRAP_T, Gl::CLAMP_TO_EDGE),
+    ] {
+        context.tex_parameteri(Gl::TEXTURE_2D, parameter, value as i32);
+    }
+
+    let framebuffer = context
+        .create_framebuffer()
+        .ok_or("Failed to create framebuffer")?;
+    context.bind_framebuffer(Gl::FRAMEBUFFER, Some(&framebuffer));
+    context.framebuffer_texture_2d(
+        Gl::FRAMEBUFFER,
+        Gl::COLOR_ATTACHMENT0,
+        Gl::TEXTURE_2D,
+        Some(&texture),
+        0,
+    );
+    let status = context.check_framebuffer_status(Gl::FRAMEBUFFER);
+    context.bind_framebuffer(Gl::FRAMEBUFFER, None);
+    if status != Gl::FRAMEBUFFER_COMPLETE {
+        return Err(format!("Incomplete density framebuffer: {status:#x}").into());
+    }
+    Ok((framebuffer, texture))
+}
+
+fn compile_shader(context: &Gl, shader_type: u32, source: &str) -> Result<WebGlShader, String> {
+    let shader = context
+        .create_shader(shader_type)
+        .ok_or_else(|| String::from("Unable to create shader object"))?;
+    context.shader_source(&shader, source);
+    context.compile_shader(&shader);
+
+    if context
+        .get_shader_parameter(&shader, Gl::COMPILE_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(shader)
+    } else {
+        Err(context
+            .get_shader_info_log(&shader)
+            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+    }
+}
+
+fn link_program(
+    context: &Gl,
+    vert_shader: &WebGlShader,
+    frag_shader: &WebGlShader,
+) -> Result<WebGlProgram, String> {
+    let program = context
+        .create_program()
+        .ok_or_else(|| String::from("Unable to create shader object"))?;
+
+    context.attach_shader(&program, vert_shader);
+    context.attach_shader(&program, frag_shader);
+    // all programs read particle attributes from the same buffer bindings
+    context.bind_attrib_location(&program, POSITION_LOCATION, "in_position");
+    context.bind_attrib_location(&program, COLOR_LOCATION, "in_color");
+    context.link_program(&program);
+
+    if context
+        .get_program_parameter(&program, Gl::LINK_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(program)
+    } else {
+        Err(context
+            .get_program_info_log(&program)
+            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+    }
+}
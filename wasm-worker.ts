@@ -1,6 +1,6 @@
 import * as Comlink from 'comlink';
 import * as Stats from 'stats.js';
-import { Simulation } from './pkg';
+import { PointerMode, RenderMode, Simulation } from './pkg';
 
 export type HandlersWrap = {
     handlers: Handlers;
@@ -9,9 +9,69 @@ export type HandlersWrap = {
 export type Handlers = {
     sim: Simulation;
     numThreads: number;
-    init: (offscreenCanvas: OffscreenCanvas, stats: Stats, simPanel: Stats.Panel, useDarkMode: boolean) => number;
+    colorAttributes: string[];
+    colormaps: string[];
+    parameters: ParameterInfo[];
+    init: (offscreenCanvas: OffscreenCanvas, stats: Stats, simPanel: Stats.Panel, diagnosticPanels: DiagnosticPanels, useDarkMode: boolean) => number;
     addBlock: () => number;
     reset: () => number;
+    loadScene: (source: string) => number;
+    setRenderMode: (mode: keyof typeof RenderMode) => void;
+    setColorBy: (attribute: string) => void;
+    setColormap: (name: string) => void;
+    setColorRange: (range: [number, number] | null) => void;
+    colorRange: () => number[];
+    pointerDown: (x: number, y: number, width: number, height: number, mode: keyof typeof PointerMode) => void;
+    pointerMove: (x: number, y: number, width: number, height: number) => void;
+    pointerUp: () => void;
+    setPaused: (paused: boolean) => void;
+    paused: () => boolean;
+    setSafeguard: (enabled: boolean) => void;
+    setTimeScale: (timeScale: number) => void;
+    stepFrame: () => void;
+    stepSubstep: () => void;
+    time: () => number;
+    particleData: () => ParticleData;
+    setParameter: (name: string, value: number) => void;
+    parameterValues: () => Record<string, number>;
+    diagnostics: () => Diagnostics;
+};
+
+// stats.js panels fed from the solver diagnostics
+export type DiagnosticPanels = {
+    densityError: Stats.Panel;
+    maxSpeed: Stats.Panel;
+};
+
+// mirrors `solver::Diagnostics`
+export type Diagnostics = {
+    time: number;
+    particles: number;
+    kinetic_energy: number;
+    potential_energy: number;
+    max_speed: number;
+    mean_speed: number;
+    mean_density_error: number;
+    max_density_error: number;
+    neighbor_histogram: Uint32Array;
+    truncated_neighbors: number;
+    boundary_particles: number;
+};
+
+// metadata of a tunable solver parameter, `name` is also a `Simulation` property
+export type ParameterInfo = {
+    name: string;
+    min: number;
+    max: number;
+    step: number;
+    default: number;
+};
+
+// views into the shared wasm memory, see `Simulation.positions` for when they go stale
+export type ParticleData = {
+    positions: Float32Array;
+    velocities: Float32Array;
+    pressures: Float32Array;
 };
 
 const initHandlers = async (): Promise<Handlers> => {
@@ -19,20 +79,46 @@ const initHandlers = async (): Promise<Handlers> => {
     await rust_wasm.default();
     const numThreads = navigator.hardwareConcurrency;
     let maxSimMs = 0;
+    let maxDensityError = 0;
+    let maxSpeed = 0;
+    // diagnostics revisit all neighbor pairs, so they are only sampled every few frames
+    const diagnosticsEvery = 10;
     // must be included to init rayon thread pool with web workers
     await rust_wasm.initThreadPool(numThreads);
+    // converts CSS pixels on a canvas of the given size to simulation units
+    const toWorld = (sim: Simulation, x: number, y: number, width: number, height: number): [number, number] =>
+        [x / width * sim.view_width, (1 - y / height) * sim.view_height];
     return Comlink.proxy({
         sim: null,
         numThreads: numThreads,
-        init(offscreenCanvas: OffscreenCanvas, stats: Stats, simPanel: Stats.Panel, useDarkMode: boolean) {
+        colorAttributes: rust_wasm.color_attributes(),
+        colormaps: rust_wasm.colormaps(),
+        parameters: rust_wasm.parameters(),
+        init(offscreenCanvas: OffscreenCanvas, stats: Stats, simPanel: Stats.Panel, diagnosticPanels: DiagnosticPanels, useDarkMode: boolean) {
             this.sim = new rust_wasm.Simulation(offscreenCanvas, useDarkMode);
+            let frame = 0;
             const step = () => {
                 stats.begin(); // collect perf data for stats.js
                 let simTimeMs = performance.now();
-                this.sim.step();
+                try {
+                    this.sim.step();
+                } catch (e) {
+                    // the simulation pauses itself when it diverges
+                    console.error(`Simulation stopped: ${e}`);
+                }
+                const recovery = this.sim.last_recovery;
+                if (recovery) {
+                    console.warn(`Safeguard: ${recovery}`);
+                }
                 simTimeMs = performance.now() - simTimeMs;
                 this.sim.draw();
                 simPanel.update(simTimeMs, (maxSimMs = Math.max(maxSimMs, simTimeMs)));
+                if (frame++ % diagnosticsEvery == 0) {
+                    const diagnostics: Diagnostics = this.sim.diagnostics();
+                    const densityError = 100 * diagnostics.max_density_error;
+                    diagnosticPanels.densityError.update(densityError, (maxDensityError = Math.max(maxDensityError, densityError)));
+                    diagnosticPanels.maxSpeed.update(diagnostics.max_speed, (maxSpeed = Math.max(maxSpeed, diagnostics.max_speed)));
+                }
                 stats.end();
                 requestAnimationFrame(step);
             }
@@ -47,6 +133,81 @@ const initHandlers = async (): Promise<Handlers> => {
             this.sim.reset();
             return this.sim.num_particles;
         },
+        loadScene(source: string) {
+            this.sim.load_scene(source);
+            return this.sim.num_particles;
+        },
+        setRenderMode(mode: keyof typeof RenderMode) {
+            this.sim.render_mode = rust_wasm.RenderMode[mode];
+        },
+        setColorBy(attribute: string) {
+            this.sim.set_color_by(attribute);
+        },
+        setColormap(name: string) {
+            this.sim.set_colormap(name);
+        },
+        // null fits the range to each frame
+        setColorRange(range: [number, number] | null) {
+            if (range) {
+                this.sim.set_color_range(range[0], range[1]);
+            } else {
+                this.sim.set_auto_color_range();
+            }
+        },
+        colorRange() {
+            return Array.from(this.sim.color_range);
+        },
+        pointerDown(x: number, y: number, width: number, height: number, mode: keyof typeof PointerMode) {
+            const [wx, wy] = toWorld(this.sim, x, y, width, height);
+            this.sim.pointer_down(wx, wy, rust_wasm.PointerMode[mode]);
+        },
+        pointerMove(x: number, y: number, width: number, height: number) {
+            const [wx, wy] = toWorld(this.sim, x, y, width, height);
+            this.sim.pointer_move(wx, wy);
+        },
+        pointerUp() {
+            this.sim.pointer_up();
+        },
+        setPaused(paused: boolean) {
+            this.sim.paused = paused;
+        },
+        // the safeguard pauses the simulation when it cannot recover
+        paused() {
+            return this.sim ? this.sim.paused : false;
+        },
+        setSafeguard(enabled: boolean) {
+            this.sim.safeguard = enabled;
+        },
+        setTimeScale(timeScale: number) {
+            this.sim.time_scale = timeScale;
+        },
+        stepFrame() {
+            this.sim.step_frame();
+        },
+        stepSubstep() {
+            this.sim.step_substep();
+        },
+        // the views are backed by a SharedArrayBuffer, so posting them does not copy the data
+        particleData() {
+            return {
+                positions: this.sim.positions,
+                velocities: this.sim.velocities,
+                pressures: this.sim.pressures,
+            };
+        },
+        setParameter(name: string, value: number) {
+            this.sim[name] = value;
+        },
+        // current values, which change when a scene is loaded or reset
+        parameterValues() {
+            return Object.fromEntries(this.parameters.map((info: ParameterInfo) => [info.name, this.sim[info.name]]));
+        },
+        diagnostics() {
+            return this.sim.diagnostics();
+        },
+        time() {
+            return this.sim ? this.sim.time : 0;
+        },
     });
 };
 